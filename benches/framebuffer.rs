@@ -0,0 +1,41 @@
+// Filling a frame column by column, one draw_pixel per pixel against the
+// spans of a ColumnWriter.
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_doom_demo::framebuffer::{Canvas, ColumnWriter, FrameBuffer};
+use rust_doom_demo::math::Vec2;
+use std::hint::black_box;
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 960;
+
+fn color(x: usize, y: usize) -> [u8; 4] {
+    [x as u8, y as u8, (x ^ y) as u8, 0xff]
+}
+
+fn columns(c: &mut Criterion) {
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
+    let mut group = c.benchmark_group("columns");
+    group.sample_size(20);
+    group.bench_function("draw_pixel", |b| {
+        b.iter(|| {
+            let frame: &mut dyn FrameBuffer = black_box(&mut canvas);
+            for x in 0..WIDTH {
+                for y in 0..HEIGHT {
+                    frame.draw_pixel(&Vec2::new(x, y), &color(x, y));
+                }
+            }
+        })
+    });
+    group.bench_function("column_writer", |b| {
+        b.iter(|| {
+            let mut writer = ColumnWriter::new(black_box(&mut canvas));
+            for x in 0..WIDTH {
+                writer.column(x, 0..HEIGHT, |y| color(x, y));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, columns);
+criterion_main!(benches);
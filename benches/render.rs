@@ -0,0 +1,44 @@
+// Frame time of the renderer at a high resolution, with the columns split in
+// 1, 2, 4... bands drawn by as many threads, and one band for each core.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_doom_demo::consts;
+use rust_doom_demo::framebuffer::{Canvas, FrameBuffer};
+use rust_doom_demo::map::Map;
+use rust_doom_demo::render::{Render, RenderConfig};
+use rust_doom_demo::texture::TextureSet;
+use std::rc::Rc;
+use std::thread;
+
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 960;
+
+fn threads(c: &mut Criterion) {
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut counts: Vec<usize> = (0..).map(|i| 1 << i).take_while(|&count| count <= cores.max(4)).collect();
+    if !counts.contains(&cores) {
+        counts.push(cores);
+        counts.sort();
+    }
+    let mut group = c.benchmark_group("threads");
+    group.sample_size(20);
+    for path in ["assets/4tw.map", "assets/rooms.ron"] {
+        let map = Map::from(path).unwrap();
+        let player = *map.player.borrow();
+        let mut canvas = Canvas::new(WIDTH as usize, HEIGHT as usize);
+        for &count in counts.iter() {
+            let config = RenderConfig { threads: count, ..RenderConfig::new(WIDTH, HEIGHT, 1) };
+            let mut render = Render::new(Rc::clone(&map.world), Rc::clone(&textures), config);
+            group.bench_with_input(BenchmarkId::new(path, count), &count, |b, _| {
+                b.iter(|| {
+                    canvas.clear(consts::BACKGROUND_COLOR);
+                    render.draw(&mut canvas, &player);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, threads);
+criterion_main!(benches);
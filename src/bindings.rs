@@ -0,0 +1,170 @@
+#![allow(dead_code)]
+// Using, d3d
+use crate::player::PlayerInput;
+// Using
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+// What a key does, moves are along the view or along the map axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    MoveNorth,
+    MoveSouth,
+    MoveWest,
+    MoveEast,
+    TurnLeft,
+    TurnRight,
+    LookUp,
+    LookDown,
+    FlyUp,
+    FlyDown,
+    ToggleFly,
+    ToggleNoclip,
+    CaptureMouse,
+    NarrowFov,
+    WidenFov,
+    Screenshot,
+    Quit,
+}
+
+// Standard moves along the map axes, classic along the view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preset {
+    Standard,
+    Classic,
+}
+
+// Keys of each action, any of them triggers it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    pub keys: BTreeMap<Action, Vec<VirtualKeyCode>>,
+}
+
+// Config file, a preset with some actions bound to other keys
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct BindingsFile {
+    #[serde(default)]
+    preset: Option<Preset>,
+    #[serde(default)]
+    keys: BTreeMap<Action, Vec<VirtualKeyCode>>,
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Io(error) => write!(f, "I/O error: {}", error),
+            BindingsError::Ron(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BindingsError::Io(error) => Some(error),
+            BindingsError::Ron(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for BindingsError {
+    fn from(error: io::Error) -> Self {
+        BindingsError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for BindingsError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        BindingsError::Ron(error)
+    }
+}
+
+impl Bindings {
+    pub fn preset(preset: Preset) -> Self {
+        use Action::*;
+        use VirtualKeyCode as Key;
+        let moves = match preset {
+            Preset::Standard => [MoveNorth, MoveSouth, MoveWest, MoveEast],
+            Preset::Classic => [MoveForward, MoveBackward, StrafeLeft, StrafeRight],
+        };
+        let keys = [
+            (moves[0], Key::W),
+            (moves[1], Key::S),
+            (moves[2], Key::A),
+            (moves[3], Key::D),
+            (TurnLeft, Key::Left),
+            (TurnRight, Key::Right),
+            (LookUp, Key::Up),
+            (LookDown, Key::Down),
+            (FlyUp, Key::R),
+            (FlyDown, Key::F),
+            (ToggleFly, Key::G),
+            (ToggleNoclip, Key::N),
+            (CaptureMouse, Key::M),
+            (NarrowFov, Key::Minus),
+            (WidenFov, Key::Equals),
+            (Screenshot, Key::F12),
+            (Quit, Key::Escape),
+        ];
+        Bindings {
+            keys: keys.iter().map(|(action, key)| (*action, vec![*key])).collect(),
+        }
+    }
+
+    pub fn from(path: &str, preset: Preset) -> Result<Self, BindingsError> {
+        Bindings::from_ron(&fs::read_to_string(path)?, preset)
+    }
+
+    // The preset of the file, else the given one, then the keys of the file
+    pub fn from_ron(text: &str, preset: Preset) -> Result<Self, BindingsError> {
+        let file: BindingsFile = ron::from_str(text)?;
+        let mut bindings = Bindings::preset(file.preset.unwrap_or(preset));
+        bindings.keys.extend(file.keys);
+        Ok(bindings)
+    }
+
+    pub fn keys(&self, action: Action) -> &[VirtualKeyCode] {
+        self.keys.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    pub fn held(&self, input: &WinitInputHelper, action: Action) -> bool {
+        self.keys(action).iter().any(|key| input.key_held(*key))
+    }
+
+    pub fn pressed(&self, input: &WinitInputHelper, action: Action) -> bool {
+        self.keys(action).iter().any(|key| input.key_pressed(*key))
+    }
+
+    // Positive action held without the negative one
+    fn axis(&self, input: &WinitInputHelper, positive: Action, negative: Action) -> i8 {
+        self.held(input, positive) as i8 - self.held(input, negative) as i8
+    }
+
+    pub fn player_input(&self, input: &WinitInputHelper) -> PlayerInput {
+        PlayerInput {
+            forward: self.axis(input, Action::MoveForward, Action::MoveBackward),
+            strafe: self.axis(input, Action::StrafeRight, Action::StrafeLeft),
+            north: self.axis(input, Action::MoveNorth, Action::MoveSouth),
+            east: self.axis(input, Action::MoveEast, Action::MoveWest),
+            fly: self.axis(input, Action::FlyUp, Action::FlyDown),
+            turn: self.axis(input, Action::TurnRight, Action::TurnLeft),
+            look: self.axis(input, Action::LookDown, Action::LookUp),
+            ..Default::default()
+        }
+    }
+}
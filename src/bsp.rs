@@ -0,0 +1,240 @@
+#![allow(dead_code)]
+// Using, d3d
+use crate::math::{lerp, Vec2};
+use crate::world::{Wall, World};
+// Using
+use serde::{Deserialize, Serialize};
+
+// Distance from a partition line under which a point lies on it
+const ON_LINE: f32 = 0.01;
+// Splitters tried at each node
+const MAX_CANDIDATES: usize = 64;
+// Cost of a split against the unbalance of the two sides
+const SPLIT_COST: usize = 8;
+
+// Piece of a wall, u is the range of the wall it covers (0..1 from point1)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Seg {
+    pub point1: Vec2<f32>,
+    pub point2: Vec2<f32>,
+    pub wall: usize,
+    pub sector: usize,
+    pub u: Vec2<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BspChild {
+    Node(usize),
+    Leaf(usize),
+}
+
+// Front is the left side of the partition line
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BspNode {
+    pub origin: Vec2<f32>,
+    pub direction: Vec2<f32>,
+    pub front: BspChild,
+    pub back: BspChild,
+}
+
+// Range of segs, no seg of a leaf hides another one from behind
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BspLeaf {
+    pub segs: Vec2<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bsp {
+    pub root: BspChild,
+    pub nodes: Vec<BspNode>,
+    pub leaves: Vec<BspLeaf>,
+    pub segs: Vec<Seg>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Front,
+    Back,
+    Split,
+}
+
+impl Seg {
+    // Seg covering the whole wall
+    pub fn whole(wall: &Wall, wall_id: usize, sector_id: usize) -> Seg {
+        Seg {
+            point1: wall.point1.as_vec::<f32>(),
+            point2: wall.point2.as_vec::<f32>(),
+            wall: wall_id,
+            sector: sector_id,
+            u: Vec2::new(0.0, 1.0),
+        }
+    }
+
+    // The point sees the back of the seg
+    pub fn behind(&self, point: &Vec2<f32>) -> bool {
+        self.direction().cross(&(*point - self.point1)) < 0.0
+    }
+
+    fn direction(&self) -> Vec2<f32> {
+        (self.point2 - self.point1).normalize()
+    }
+
+    // Signed distance of the points from the line of the seg
+    fn distances(&self, origin: &Vec2<f32>, direction: &Vec2<f32>) -> (f32, f32) {
+        (direction.cross(&(self.point1 - *origin)), direction.cross(&(self.point2 - *origin)))
+    }
+
+    fn side(&self, origin: &Vec2<f32>, direction: &Vec2<f32>) -> Side {
+        let (d1, d2) = self.distances(origin, direction);
+        if d1.abs() <= ON_LINE && d2.abs() <= ON_LINE {
+            // On the line, in front when it has the same direction
+            if 0.0 <= self.direction().dot(direction) { Side::Front } else { Side::Back }
+        } else if -ON_LINE <= d1 && -ON_LINE <= d2 {
+            Side::Front
+        } else if d1 <= ON_LINE && d2 <= ON_LINE {
+            Side::Back
+        } else {
+            Side::Split
+        }
+    }
+
+    // Cut by the line, the piece of point1 first
+    fn split(&self, origin: &Vec2<f32>, direction: &Vec2<f32>) -> (Seg, Seg) {
+        let (d1, d2) = self.distances(origin, direction);
+        let s = d1 / (d1 - d2);
+        let point = self.point1 + (self.point2 - self.point1) * s;
+        let u = lerp(self.u.x, self.u.y, s);
+        (
+            Seg { point2: point, u: Vec2::new(self.u.x, u), ..*self },
+            Seg { point1: point, u: Vec2::new(u, self.u.y), ..*self },
+        )
+    }
+
+    // Some point of the other seg is behind this one
+    fn hides(&self, other: &Seg) -> bool {
+        let (d1, d2) = other.distances(&self.point1, &self.direction());
+        d1 < -ON_LINE || d2 < -ON_LINE
+    }
+}
+
+impl Bsp {
+    // Node builder, walls out of every sector are left out
+    pub fn build(world: &World) -> Bsp {
+        let mut segs = Vec::new();
+        for (sector_id, sector) in world.sectors.iter().enumerate() {
+            if world.sector_walls(sector).is_none() {
+                continue;
+            }
+            for wall_id in sector.wall.x as usize..sector.wall.y as usize {
+                let wall = &world.walls[wall_id];
+                if wall.point1 == wall.point2 {
+                    continue;
+                }
+                segs.push(Seg::whole(wall, wall_id, sector_id));
+            }
+        }
+        let mut bsp = Bsp {
+            root: BspChild::Leaf(0),
+            nodes: vec![],
+            leaves: vec![],
+            segs: vec![],
+        };
+        bsp.root = bsp.build_child(segs);
+        bsp
+    }
+
+    fn build_child(&mut self, segs: Vec<Seg>) -> BspChild {
+        let splitter = match choose_splitter(&segs) {
+            Some(splitter) => splitter,
+            None => {
+                // Leaf
+                let first = self.segs.len();
+                self.segs.extend(segs);
+                self.leaves.push(BspLeaf { segs: Vec2::new(first, self.segs.len()) });
+                return BspChild::Leaf(self.leaves.len() - 1);
+            }
+        };
+        let origin = segs[splitter].point1;
+        let direction = segs[splitter].direction();
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for seg in segs.iter() {
+            match seg.side(&origin, &direction) {
+                Side::Front => front.push(*seg),
+                Side::Back => back.push(*seg),
+                Side::Split => {
+                    let (first, second) = seg.split(&origin, &direction);
+                    if 0.0 < direction.cross(&(seg.point1 - origin)) {
+                        front.push(first);
+                        back.push(second);
+                    } else {
+                        back.push(first);
+                        front.push(second);
+                    }
+                }
+            }
+        }
+        // Reserve the node, then the children
+        let index = self.nodes.len();
+        self.nodes.push(BspNode {
+            origin,
+            direction,
+            front: BspChild::Leaf(0),
+            back: BspChild::Leaf(0),
+        });
+        self.nodes[index].front = self.build_child(front);
+        self.nodes[index].back = self.build_child(back);
+        BspChild::Node(index)
+    }
+
+    // Leaves from the nearest to the farthest from the point, until visit returns false
+    pub fn walk<'a, F: FnMut(&'a [Seg]) -> bool>(&'a self, point: &Vec2<f32>, mut visit: F) {
+        let mut stack = vec![self.root];
+        while let Some(child) = stack.pop() {
+            match child {
+                BspChild::Leaf(index) => {
+                    let segs = self.leaves[index].segs;
+                    if !visit(&self.segs[segs.x..segs.y]) {
+                        return;
+                    }
+                }
+                BspChild::Node(index) => {
+                    let node = &self.nodes[index];
+                    // The far side is visited last
+                    if 0.0 <= node.direction.cross(&(*point - node.origin)) {
+                        stack.push(node.back);
+                        stack.push(node.front);
+                    } else {
+                        stack.push(node.front);
+                        stack.push(node.back);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Best partition among the segs hiding others, none when they make a leaf
+fn choose_splitter(segs: &[Seg]) -> Option<usize> {
+    let candidates: Vec<usize> = (0..segs.len())
+        .filter(|&i| segs.iter().any(|other| segs[i].hides(other)))
+        .collect();
+    let step = candidates.len().div_ceil(MAX_CANDIDATES).max(1);
+    candidates
+        .iter()
+        .step_by(step)
+        .min_by_key(|&&i| {
+            let origin = segs[i].point1;
+            let direction = segs[i].direction();
+            let (mut front, mut back, mut splits) = (0, 0, 0);
+            for seg in segs {
+                match seg.side(&origin, &direction) {
+                    Side::Front => front += 1,
+                    Side::Back => back += 1,
+                    Side::Split => splits += 1,
+                }
+            }
+            splits * SPLIT_COST + front.max(back) - front.min(back)
+        })
+        .copied()
+}
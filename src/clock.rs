@@ -0,0 +1,43 @@
+#![allow(dead_code)]
+// Using
+use std::time::Duration;
+
+// Fixed timestep of the simulation, frames of any length are turned into
+// whole ticks and the time left is carried to the next frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    pub tick: Duration,
+    accumulator: Duration,
+    max_frame: Duration,
+}
+
+impl Clock {
+    pub fn new(rate: u32, max_frame: Duration) -> Self {
+        Clock {
+            tick: Duration::from_secs(1) / rate.max(1),
+            accumulator: Duration::ZERO,
+            max_frame,
+        }
+    }
+
+    // Seconds of a tick, the time step of the player
+    pub fn delta(&self) -> f32 {
+        self.tick.as_secs_f32()
+    }
+
+    // Ticks to run after a frame, a long stall is cut to max_frame
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed.min(self.max_frame);
+        let mut ticks = 0;
+        while self.accumulator >= self.tick {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    // Part of the next tick already elapsed, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick.as_secs_f32()
+    }
+}
@@ -16,12 +16,26 @@ pub const H_HEIGHT : u32 = HEIGHT / 2;
 pub const BACKGROUND_COLOR: [u8; 4] = [0x22,0x22,0xff,0xff];
 pub const PLANE_TILE_FACTOR: f32 = 1.0 / 4.0; 
 
-// Player
-pub const MOVE_VELOCITY : i32 = 2;
-pub const ROTATION_VELOCITY : i32 = 1;
-pub const UPDOWN_VELOCITY : i32 = 1;
+// Game loop, ticks per second like Doom
+pub const TICK_RATE : u32 = 35;
+pub const MAX_FRAME_TIME : f32 = 0.25;
+
+// Player, per second
+pub const MOVE_VELOCITY : f32 = 70.0;
+pub const ROTATION_VELOCITY : f32 = 70.0;
+pub const UPDOWN_VELOCITY : f32 = 35.0;
+pub const MAX_UPDOWN : f32 = 60.0;
+pub const MOUSE_SENSITIVITY : f32 = 0.2;
+pub const PLAYER_RADIUS : f32 = 4.0;
+pub const PLAYER_HEIGHT : f32 = 24.0;
+pub const EYE_HEIGHT : f32 = 16.0;
+pub const STEP_HEIGHT : f32 = 12.0;
+pub const GRAVITY : f32 = 600.0;
 
 // Camera
 pub const FOV : f32 = 77.3;
 pub const H_FOV : f32 = FOV / 2.0;
+pub const MIN_FOV : f32 = 30.0;
+pub const MAX_FOV : f32 = 150.0;
+pub const FOV_STEP : f32 = 5.0;
 pub const UPDOWN_FACTOR : f32 = 60.0;
\ No newline at end of file
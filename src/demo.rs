@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+// Using, d3d
+use crate::clock::Clock;
+use crate::player::{Player, PlayerInput};
+use crate::world::World;
+// Using
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+pub const DEMO_VERSION: u32 = 1;
+
+// Input of every tick from a start, the same inputs on the same map give the
+// same path. Ticks with the same input are stored once with their count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Demo {
+    pub version: u32,
+    pub tick_rate: u32,
+    pub start: Player,
+    runs: Vec<(u32, PlayerInput)>,
+}
+
+#[derive(Debug)]
+pub enum DemoError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+    RonWrite(ron::Error),
+    Version { found: u32, supported: u32 },
+    TickRate { found: u32, expected: u32 },
+}
+
+impl fmt::Display for DemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DemoError::Io(error) => write!(f, "I/O error: {}", error),
+            DemoError::Ron(error) => write!(f, "{}", error),
+            DemoError::RonWrite(error) => write!(f, "{}", error),
+            DemoError::Version { found, supported } =>
+                write!(f, "demo version {} is newer than the supported version {}", found, supported),
+            DemoError::TickRate { found, expected } =>
+                write!(f, "demo recorded at {} ticks per second, expected {}", found, expected),
+        }
+    }
+}
+
+impl std::error::Error for DemoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DemoError::Io(error) => Some(error),
+            DemoError::Ron(error) => Some(error),
+            DemoError::RonWrite(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DemoError {
+    fn from(error: io::Error) -> Self {
+        DemoError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for DemoError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        DemoError::Ron(error)
+    }
+}
+
+impl From<ron::Error> for DemoError {
+    fn from(error: ron::Error) -> Self {
+        DemoError::RonWrite(error)
+    }
+}
+
+impl Demo {
+    pub fn new(start: Player, tick_rate: u32) -> Self {
+        Demo {
+            version: DEMO_VERSION,
+            tick_rate,
+            start,
+            runs: vec![],
+        }
+    }
+
+    pub fn record(&mut self, input: &PlayerInput) {
+        match self.runs.last_mut() {
+            Some((count, last)) if last == input => *count += 1,
+            _ => self.runs.push((1, *input)),
+        }
+    }
+
+    // Ticks recorded
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|(count, _)| *count as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = &PlayerInput> {
+        self.runs.iter().flat_map(|(count, input)| std::iter::repeat_n(input, *count as usize))
+    }
+
+    // The start and the player after each tick
+    pub fn path(&self, world: &World) -> Vec<Player> {
+        let dt = Clock::new(self.tick_rate, Duration::ZERO).delta();
+        let mut player = self.start;
+        let mut path = vec![player];
+        for input in self.inputs() {
+            player.tick(world, input, dt);
+            path.push(player);
+        }
+        path
+    }
+
+    // Only demos of the given tick rate play back the same
+    pub fn from(path: &str, tick_rate: u32) -> Result<Demo, DemoError> {
+        let demo = Demo::from_ron(&fs::read_to_string(path)?)?;
+        if demo.tick_rate != tick_rate {
+            return Err(DemoError::TickRate { found: demo.tick_rate, expected: tick_rate });
+        }
+        Ok(demo)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Demo, DemoError> {
+        let demo: Demo = ron::from_str(text)?;
+        if demo.version > DEMO_VERSION {
+            return Err(DemoError::Version { found: demo.version, supported: DEMO_VERSION });
+        }
+        Ok(demo)
+    }
+
+    pub fn to_ron(&self) -> Result<String, DemoError> {
+        let config = ron::ser::PrettyConfig::new().depth_limit(2);
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), DemoError> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+#![allow(dead_code)]
+// Using, d3d
+use crate::math::Vec2;
+use crate::tga::{decode_tga, encode_tga};
+// Using
+use pixels::Pixels;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::ops::Range;
+use std::path::Path;
+
+// RGBA target of the renderer, rows are stored top to bottom while
+// positions have their origin in the bottom left corner.
+pub trait FrameBuffer {
+    fn dimensions(&self) -> Vec2<usize>;
+
+    fn buffer(&self) -> &[u8];
+
+    fn buffer_mut(&mut self) -> &mut [u8];
+
+    fn channels(&self) -> usize {
+        4
+    }
+
+    fn clear(&mut self, color: [u8; 4]) {
+        for pixel in self.buffer_mut().chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    fn draw_pixel(&mut self, position: &Vec2<usize>, color: &[u8]) {
+        let size = self.dimensions();
+        let channels = self.channels();
+        if position.x >= size.x || position.y >= size.y {
+            return;
+        }
+        let row_size = size.x * channels;
+        let offset = (size.y - position.y - 1) * row_size + position.x * channels;
+        let frame = self.buffer_mut();
+        frame[offset..offset + color.len()].copy_from_slice(color);
+    }
+}
+
+// Window backend
+impl FrameBuffer for Pixels {
+    fn dimensions(&self) -> Vec2<usize> {
+        let size = self.texture().size();
+        Vec2::new(size.width as usize, size.height as usize)
+    }
+
+    fn buffer(&self) -> &[u8] {
+        self.frame()
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        self.frame_mut()
+    }
+
+    fn channels(&self) -> usize {
+        self.texture().format().block_size(None).unwrap() as usize
+    }
+}
+
+// CPU backend, renders without a window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    pub dimensions: Vec2<usize>,
+    pub data: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            dimensions: Vec2::new(width, height),
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    // Reads a TGA written by save_frame, or any 24/32-bit TGA
+    pub fn load(path: &str) -> io::Result<Self> {
+        let in_tga = fs::read(path)?;
+        let mut data = Vec::new();
+        let (mut width, mut height, mut format, mut colors) = (0, 0, 0, 0);
+        if in_tga.len() < 18 || !decode_tga(&mut data, &mut width, &mut height, &mut format, &mut colors, &in_tga)
+        || (colors != 3 && colors != 4) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported TGA image"));
+        }
+        // Decoded rows are bottom to top
+        let mut canvas = Canvas::new(width, height);
+        for (y, row) in data.chunks_exact(width * colors as usize).rev().enumerate() {
+            for (x, pixel) in row.chunks_exact(colors as usize).enumerate() {
+                let offset = (y * width + x) * 4;
+                canvas.data[offset..offset + colors as usize].copy_from_slice(pixel);
+                if colors == 3 {
+                    canvas.data[offset + 3] = 0xff;
+                }
+            }
+        }
+        Ok(canvas)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let offset = (y * self.dimensions.x + x) * 4;
+        &self.data[offset..offset + 4]
+    }
+}
+
+impl FrameBuffer for Canvas {
+    fn dimensions(&self) -> Vec2<usize> {
+        self.dimensions
+    }
+
+    fn buffer(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+// Vertical spans written straight into the bytes of a frame, the size and
+// the layout are read once when the frame is locked.
+pub struct ColumnWriter<'a> {
+    // Rows from the top, only the bytes of the columns written
+    rows: Vec<&'a mut [u8]>,
+    size: Vec2<usize>,
+    channels: usize,
+    // Frame column of the first column of the rows
+    x: usize,
+}
+
+impl<'a> ColumnWriter<'a> {
+    pub fn new(frame: &'a mut dyn FrameBuffer) -> Self {
+        let size = frame.dimensions();
+        let channels = frame.channels();
+        ColumnWriter::from_slice(frame.buffer_mut(), size, channels, 0)
+    }
+
+    pub fn from_slice(data: &'a mut [u8], size: Vec2<usize>, channels: usize, x: usize) -> Self {
+        assert!(size.x * size.y * channels <= data.len(), "frame smaller than its size");
+        ColumnWriter {
+            rows: data.chunks_exact_mut((size.x * channels).max(1)).take(size.y).collect(),
+            size,
+            channels,
+            x,
+        }
+    }
+
+    // A writer for each band of columns, sorted from the left and apart, each
+    // writes its own bytes of the frame. Positions stay in the whole frame
+    pub fn bands(frame: &'a mut dyn FrameBuffer, columns: &[Range<usize>]) -> Vec<ColumnWriter<'a>> {
+        let size = frame.dimensions();
+        let channels = frame.channels();
+        let mut writers: Vec<ColumnWriter> = columns
+            .iter()
+            .map(|columns| ColumnWriter {
+                rows: Vec::with_capacity(size.y),
+                size: Vec2::new(columns.len(), size.y),
+                channels,
+                x: columns.start,
+            })
+            .collect();
+        for mut row in frame.buffer_mut().chunks_exact_mut((size.x * channels).max(1)).take(size.y) {
+            let mut x = 0;
+            for (writer, columns) in writers.iter_mut().zip(columns) {
+                assert!(x <= columns.start && columns.end <= size.x, "bands out of order or out of the frame");
+                let (_, rest) = std::mem::take(&mut row).split_at_mut((columns.start - x) * channels);
+                let (band, rest) = rest.split_at_mut(columns.len() * channels);
+                writer.rows.push(band);
+                row = rest;
+                x = columns.end;
+            }
+        }
+        writers
+    }
+
+    pub fn dimensions(&self) -> Vec2<usize> {
+        self.size
+    }
+
+    // Row from the top and offset in it of the pixel, none outside of the frame
+    fn offset(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let x = x.checked_sub(self.x)?;
+        if x >= self.size.x || y >= self.size.y {
+            return None;
+        }
+        Some((self.size.y - y - 1, x * self.channels))
+    }
+
+    pub fn pixel(&mut self, x: usize, y: usize, color: &[u8]) {
+        if let Some((row, offset)) = self.offset(x, y) {
+            let length = color.len().min(self.channels);
+            self.rows[row][offset..offset + length].copy_from_slice(&color[..length]);
+        }
+    }
+
+    // Rows of the column from bottom to top, color gives the color of each row
+    pub fn column<F: FnMut(usize) -> [u8; 4]>(&mut self, x: usize, rows: Range<usize>, mut color: F) {
+        let rows = rows.start..rows.end.min(self.size.y);
+        let start = match self.offset(x, rows.start) {
+            Some(start) if !rows.is_empty() => start,
+            _ => return,
+        };
+        let length = self.channels.min(4);
+        // Rows up the screen are rows back in memory
+        for (i, y) in rows.enumerate() {
+            self.rows[start.0 - i][start.1..start.1 + length].copy_from_slice(&color(y)[..length]);
+        }
+    }
+
+    // As column, rows with an alpha of zero are left as they are
+    pub fn masked_column<F: FnMut(usize) -> [u8; 4]>(&mut self, x: usize, rows: Range<usize>, mut color: F) {
+        let rows = rows.start..rows.end.min(self.size.y);
+        let start = match self.offset(x, rows.start) {
+            Some(start) if !rows.is_empty() => start,
+            _ => return,
+        };
+        let length = self.channels.min(4);
+        for (i, y) in rows.enumerate() {
+            let color = color(y);
+            if color[3] != 0 {
+                self.rows[start.0 - i][start.1..start.1 + length].copy_from_slice(&color[..length]);
+            }
+        }
+    }
+
+    // As column, each color mixed by its alpha with the pixel under it, which keeps its alpha
+    pub fn blended_column<F: FnMut(usize) -> [u8; 4]>(&mut self, x: usize, rows: Range<usize>, mut color: F) {
+        let rows = rows.start..rows.end.min(self.size.y);
+        let start = match self.offset(x, rows.start) {
+            Some(start) if !rows.is_empty() => start,
+            _ => return,
+        };
+        let length = self.channels.min(3);
+        for (i, y) in rows.enumerate() {
+            let color = color(y);
+            let alpha = color[3] as u32;
+            if alpha == 0 {
+                continue;
+            }
+            for (pixel, channel) in self.rows[start.0 - i][start.1..start.1 + length].iter_mut().zip(color) {
+                *pixel = ((channel as u32 * alpha + *pixel as u32 * (0xff - alpha) + 0x7f) / 0xff) as u8;
+            }
+        }
+    }
+}
+
+// Writes the frame as TGA (RLE compressed) or PNG, chosen by extension
+pub fn save_frame(frame: &dyn FrameBuffer, path: &str) -> io::Result<()> {
+    let size = frame.dimensions();
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("tga") => {
+            let mut out_tga = Vec::new();
+            if !encode_tga(&mut out_tga, frame.buffer(), size.x, size.y, 4, true) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "frame can not be encoded as TGA"));
+            }
+            fs::write(path, out_tga)
+        },
+        Some("png") => {
+            let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), size.x as u32, size.y as u32);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(io::Error::other)?;
+            writer.write_image_data(frame.buffer()).map_err(io::Error::other)
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "screenshots must be .tga or .png files")),
+    }
+}
@@ -0,0 +1,16 @@
+pub mod consts;
+pub mod math;
+pub mod world;
+pub mod bsp;
+pub mod player;
+pub mod bindings;
+pub mod clock;
+pub mod demo;
+pub mod timedemo;
+pub mod render;
+pub mod framebuffer;
+pub mod windows;
+pub mod map;
+pub mod tga;
+pub mod texture;
+pub mod wad;
@@ -1,26 +1,25 @@
-mod consts;
-mod math;
-mod world;
-mod player;
-mod render;
-mod windows;
-mod map;
-mod tga;
-mod texture;
-
 // Using d3
-use crate::map::Map;
-use crate::player::Player;
-use crate::render::Render;
-use crate::texture::TextureSet;
+use rust_doom_demo::consts;
+use rust_doom_demo::framebuffer::{save_frame, Canvas, FrameBuffer};
+use rust_doom_demo::windows;
+use rust_doom_demo::map::{Map, MapError, MapFormat};
+use rust_doom_demo::clock::Clock;
+use rust_doom_demo::bindings::{Action, Bindings, Preset};
+use rust_doom_demo::demo::Demo;
+use rust_doom_demo::player::{MouseSettings, Player, PlayerInput};
+use rust_doom_demo::render::{Render, RenderConfig};
+use rust_doom_demo::texture::TextureSet;
+use rust_doom_demo::timedemo;
+use rust_doom_demo::wad::Wad;
 // Using
 use winit::{
-    event::{Event, VirtualKeyCode},
+    event::{DeviceEvent, Event},
     event_loop::{ControlFlow, EventLoop},
 };
 use winit_input_helper::WinitInputHelper;
 use clap::{Command, Arg, ArgMatches, ArgAction};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 fn shell_args() -> ArgMatches {
     Command::new("Rust-doom-demo")
@@ -32,63 +31,330 @@ fn shell_args() -> ArgMatches {
         .long("map")
         .required(true)
         .help("Map path"))
+    .arg(Arg::new("level")
+        .short('l')
+        .long("level")
+        .required(false)
+        .help("Level to load from a WAD map (E1M1, MAP01...), the first one by default"))
     .arg(Arg::new("textures")
         .short('t')
         .long("textures")
-        .required(true)
+        .required_unless_present("convert")
         .help("Textures path"))
     .arg(Arg::new("classic")
         .short('c')
         .long("classic")
         .required(false)
         .action(ArgAction::SetTrue)
-        .help("Enable classic controller"))
+        .help("Enable classic controller, the preset of the key bindings"))
+    .arg(Arg::new("bindings")
+        .short('b')
+        .long("bindings")
+        .required(false)
+        .help("Key bindings path (.ron), a preset and the keys of some actions"))
+    .arg(Arg::new("sensitivity")
+        .long("sensitivity")
+        .required(false)
+        .value_parser(clap::value_parser!(f32))
+        .help("Degrees of turn for each unit of mouse motion"))
+    .arg(Arg::new("invert-y")
+        .long("invert-y")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Moving the mouse up looks down"))
+    .arg(Arg::new("interpolate")
+        .long("interpolate")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Draw the camera between the last two ticks of the simulation"))
+    .arg(Arg::new("check")
+        .long("check")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Validate the map against the textures and exit"))
+    .arg(Arg::new("convert")
+        .long("convert")
+        .required(false)
+        .help("Write the map to the given path, in the format of its extension (.map or .ron), and exit"))
+    .arg(Arg::new("nodes")
+        .long("nodes")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Build the BSP tree of the map and render with it, --convert stores it in a .ron map"))
+    .arg(Arg::new("width")
+        .long("width")
+        .required(false)
+        .value_parser(clap::value_parser!(u32).range(1..))
+        .help("Width of the frame in pixels"))
+    .arg(Arg::new("height")
+        .long("height")
+        .required(false)
+        .value_parser(clap::value_parser!(u32).range(1..))
+        .help("Height of the frame in pixels"))
+    .arg(Arg::new("scale")
+        .long("scale")
+        .required(false)
+        .value_parser(clap::value_parser!(u32).range(1..))
+        .help("Window pixels for each frame pixel, the frame follows the window when it is resized"))
+    .arg(Arg::new("fov")
+        .long("fov")
+        .required(false)
+        .value_parser(clap::value_parser!(f32))
+        .help("Horizontal field of view in degrees"))
+    .arg(Arg::new("aspect-correct")
+        .long("aspect-correct")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Keep the vertical view of a 4:3 frame, wide frames see more on the sides"))
+    .arg(Arg::new("threads")
+        .long("threads")
+        .required(false)
+        .value_parser(clap::value_parser!(usize))
+        .help("Threads drawing bands of columns, one per core by default"))
+    .arg(Arg::new("recorddemo")
+        .long("recorddemo")
+        .required(false)
+        .help("Record the input of every tick to the given .ron path, written when the window closes"))
+    .arg(Arg::new("playdemo")
+        .long("playdemo")
+        .required(false)
+        .help("Play back a recorded demo from its start, the keys take over when it ends"))
+    .arg(Arg::new("timedemo")
+        .long("timedemo")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .help("Draw the path of --playdemo, or an orbit around the player start, as fast as possible and report the frame times"))
+    .arg(Arg::new("screenshot")
+        .long("screenshot")
+        .required(false)
+        .help("Render one frame from the player start to the given .tga or .png path and exit"))
     .get_matches()
 }
 
+fn load_map(map_path: &str, level: Option<&String>, texset: Option<&TextureSet>) -> Result<Map, MapError> {
+    match MapFormat::from_path(map_path) {
+        MapFormat::Wad => Map::from_wad(&Wad::from(map_path)?, level.map(|level| level.as_str()), texset),
+        _ => Map::from(map_path),
+    }
+}
+
+fn load_textures(textures_path: &str) -> TextureSet {
+    let textures = if textures_path.to_ascii_lowercase().ends_with(".wad") {
+        Wad::from(textures_path)
+            .and_then(|wad| TextureSet::from_wad(&wad))
+            .map_err(|error| error.to_string())
+    } else {
+        TextureSet::from(textures_path).ok_or_else(|| String::from("not a readable directory of textures"))
+    };
+    match textures {
+        Ok(texset) => texset,
+        Err(error) => {
+            eprintln!("Unable to load textures {}: {}", textures_path, error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn render_config(matches: &ArgMatches) -> RenderConfig {
+    let default = RenderConfig::default();
+    let mut config = RenderConfig::new(
+        *matches.get_one::<u32>("width").unwrap_or(&default.width),
+        *matches.get_one::<u32>("height").unwrap_or(&default.height),
+        *matches.get_one::<u32>("scale").unwrap_or(&default.scale),
+    );
+    config.set_fov(*matches.get_one::<f32>("fov").unwrap_or(&default.fov));
+    config.aspect_correct = matches.get_flag("aspect-correct");
+    config.threads = *matches.get_one::<usize>("threads").unwrap_or(&default.threads);
+    config
+}
+
+fn check(map_path: &str, map: &Map, texset: &TextureSet) -> i32 {
+    let errors = map.world.validate(texset);
+    for error in errors.iter() {
+        eprintln!("{}: {}", map_path, error);
+    }
+    if errors.is_empty() {
+        println!("{}: ok", map_path);
+        0
+    } else {
+        eprintln!("{}: {} problem(s) found", map_path, errors.len());
+        1
+    }
+}
+
+fn screenshot(path: &str, frame: &dyn FrameBuffer) -> bool {
+    match save_frame(frame, path) {
+        Ok(_) => {
+            println!("Screenshot saved to {}", path);
+            true
+        }
+        Err(error) => {
+            eprintln!("Unable to save screenshot {}: {}", path, error);
+            false
+        }
+    }
+}
+
+// First screenshot-N.tga not yet in the working directory
+fn next_screenshot_path() -> String {
+    (0..)
+        .map(|n| format!("screenshot-{}.tga", n))
+        .find(|path| !std::path::Path::new(path).exists())
+        .unwrap()
+}
+
 fn main() {
     let matches = shell_args();
-    let classic = matches.get_flag("classic");
-    let map_path = matches.get_one::<String>("map").unwrap();
-    let textures_path = matches.get_one::<String>("textures").unwrap();
-    let map =  match  Map::from(map_path) {
-        Some(map) => map,
-        _ => panic!("Unable to load map {:?}", map_path),
+    let preset = if matches.get_flag("classic") { Preset::Classic } else { Preset::Standard };
+    let bindings = match matches.get_one::<String>("bindings") {
+        Some(path) => match Bindings::from(path, preset) {
+            Ok(bindings) => bindings,
+            Err(error) => {
+                eprintln!("Unable to load bindings {}: {}", path, error);
+                std::process::exit(1);
+            }
+        },
+        None => Bindings::preset(preset),
     };
-    let texset = match TextureSet::from(textures_path) {
-        Some(texset) => Rc::new(texset),
-        _ => panic!("Unable to load textures {:?}", textures_path),
+    let interpolate = matches.get_flag("interpolate");
+    let mouse = MouseSettings {
+        sensitivity: *matches.get_one::<f32>("sensitivity").unwrap_or(&MouseSettings::default().sensitivity),
+        invert_y: matches.get_flag("invert-y"),
     };
+    let map_path = matches.get_one::<String>("map").unwrap();
+    let texset = matches.get_one::<String>("textures").map(|path| Rc::new(load_textures(path)));
+    let mut map = match load_map(map_path, matches.get_one::<String>("level"), texset.as_deref()) {
+        Ok(map) => map,
+        Err(error) => {
+            eprintln!("Unable to load map {}: {}", map_path, error);
+            std::process::exit(1);
+        }
+    };
+    // Node builder
+    if matches.get_flag("nodes") {
+        map.build_bsp();
+    }
+    // Convert only
+    if let Some(output_path) = matches.get_one::<String>("convert") {
+        if let Err(error) = map.save(output_path) {
+            eprintln!("Unable to write map {}: {}", output_path, error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let texset = texset.unwrap();
+    // Check only
+    if matches.get_flag("check") {
+        std::process::exit(check(map_path, &map, &texset));
+    }
+    // Render
+    let config = render_config(&matches);
+    let mut render = Render::new(map.world, texset, config);
+    if let Some(bsp) = map.bsp {
+        render = render.with_bsp(bsp);
+    }
+
+    // Demo to play back
+    let demo = matches.get_one::<String>("playdemo").map(|path| match Demo::from(path, consts::TICK_RATE) {
+        Ok(demo) => demo,
+        Err(error) => {
+            eprintln!("Unable to load demo {}: {}", path, error);
+            std::process::exit(1);
+        }
+    });
+
+    // Benchmark
+    if matches.get_flag("timedemo") {
+        let path = match &demo {
+            Some(demo) => demo.path(&render.world),
+            None => timedemo::orbit(&map.player.borrow(), timedemo::ORBIT_FRAMES),
+        };
+        match timedemo::run(&mut render, &path) {
+            Some(stats) => println!("{}", stats),
+            None => println!("No frames to draw"),
+        }
+        return;
+    }
+
+    // Single frame, at the end of the demo
+    if let Some(path) = matches.get_one::<String>("screenshot") {
+        let player = match &demo {
+            Some(demo) => *demo.path(&render.world).last().unwrap(),
+            None => *map.player.borrow(),
+        };
+        let mut canvas = Canvas::new(config.width as usize, config.height as usize);
+        canvas.clear(consts::BACKGROUND_COLOR);
+        render.draw(&mut canvas, &player);
+        std::process::exit(if screenshot(path, &canvas) { 0 } else { 1 });
+    }
+
     // Inputs
     let mut input: WinitInputHelper = WinitInputHelper::new();
+    let mut take_screenshot = false;
     let event_loop = EventLoop::new();
 
+    // Simulation runs at a fixed rate, frames draw as fast as they can
+    let mut clock = Clock::new(consts::TICK_RATE, Duration::from_secs_f32(consts::MAX_FRAME_TIME));
+    let mut last_frame = Instant::now();
+    // Mouse look while the cursor is captured
+    let mut mouse_captured = false;
+    let mut mouse_motion = (0.0, 0.0);
+    // Toggles pressed since the last tick
+    let mut pending = PlayerInput::default();
+
+    // Demos
+    let mut playback = demo.map(|demo| {
+        *map.player.borrow_mut() = demo.start;
+        demo.inputs().copied().collect::<Vec<_>>().into_iter()
+    });
+    let mut recording = matches.get_one::<String>("recorddemo")
+        .map(|path| (path.clone(), Demo::new(*map.player.borrow(), consts::TICK_RATE)));
+    let mut previous = *map.player.borrow();
+
     // Window
+    let (window_width, window_height) = config.window_size();
     let window = windows::build_windows(
         String::from("Doom style engine"),
-        consts::SCREE_WIDTH,
-        consts::SCREE_HEIGHT,
+        window_width,
+        window_height,
         &event_loop,
     )
     .unwrap();
 
     // Surface
-    let mut pixels = windows::pixes_from_size(&window, consts::WIDTH, consts::HEIGHT).unwrap();
-
-    // Render
-    let mut render = Render::new(map.world, texset);
+    let mut pixels = windows::pixes_from_size(&window, config.width, config.height).unwrap();
 
     // Main loop
     event_loop.run(
         move |event: Event<'_, ()>, _, control_flow: &mut ControlFlow| {
             // Get player
             let mut player: std::cell::RefMut<'_, Player> = map.player.borrow_mut();
+            // Raw motion, also when the cursor can not move
+            if let Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } = &event {
+                if mouse_captured {
+                    mouse_motion.0 += delta.0;
+                    mouse_motion.1 += delta.1;
+                }
+            }
             // Event
             match event {
+                Event::LoopDestroyed => {
+                    if let Some((path, demo)) = recording.take() {
+                        match demo.save(&path) {
+                            Ok(_) => println!("Demo of {} ticks saved to {}", demo.len(), path),
+                            Err(error) => eprintln!("Unable to save demo {}: {}", path, error),
+                        }
+                    }
+                }
                 // Winit_input_helper doesn't support this event
                 Event::RedrawRequested(_) => {
-                    windows::clear_background(&mut pixels, consts::BACKGROUND_COLOR);
-                    render.draw(&mut pixels, &player);
+                    let camera = if interpolate { previous.interpolate(&player, clock.alpha()) } else { *player };
+                    pixels.clear(consts::BACKGROUND_COLOR);
+                    render.draw(&mut pixels, &camera);
+                    if take_screenshot {
+                        screenshot(&next_screenshot_path(), &pixels);
+                        take_screenshot = false;
+                    }
                     if let Err(_) = pixels.render() {
                         *control_flow = ControlFlow::Exit;
                         return;
@@ -98,16 +364,77 @@ fn main() {
                     // Input
                     if input.update(&event) {
                         // Close events
-                        if input.key_pressed(VirtualKeyCode::Escape) || input.close_requested() {
+                        if bindings.pressed(&input, Action::Quit) || input.close_requested() {
                             *control_flow = ControlFlow::Exit;
                             return;
                         }
-                        // Player inputs
-                        if classic {
-                            player.execute_input_classic(&event, &input);
-                        } else {
-                            player.execute_input_standard(&event, &input)
+                        // Frame follows the window
+                        if let Some(size) = input.window_resized() {
+                            let width = (size.width / config.scale).max(1);
+                            let height = (size.height / config.scale).max(1);
+                            if pixels.resize_surface(size.width, size.height).is_err()
+                            || pixels.resize_buffer(width, height).is_err() {
+                                *control_flow = ControlFlow::Exit;
+                                return;
+                            }
+                            render.resize(width, height);
+                        }
+                        // Field of view
+                        if bindings.pressed(&input, Action::NarrowFov) {
+                            render.config.set_fov(render.config.fov - consts::FOV_STEP);
+                        }
+                        if bindings.pressed(&input, Action::WidenFov) {
+                            render.config.set_fov(render.config.fov + consts::FOV_STEP);
+                        }
+                        // Walk through the walls
+                        if bindings.pressed(&input, Action::ToggleNoclip) {
+                            pending.toggle_noclip = !pending.toggle_noclip;
+                        }
+                        // Back to the floor after R and F
+                        if bindings.pressed(&input, Action::ToggleFly) {
+                            pending.toggle_fly = !pending.toggle_fly;
+                        }
+                        // Mouse look
+                        if bindings.pressed(&input, Action::CaptureMouse) {
+                            mouse_captured = !mouse_captured;
+                            mouse_motion = (0.0, 0.0);
+                            if let Err(error) = windows::capture_cursor(&window, mouse_captured) {
+                                eprintln!("Unable to capture the cursor: {}", error);
+                            }
+                        }
+                        // Screenshot of the next frame
+                        if bindings.pressed(&input, Action::Screenshot) {
+                            take_screenshot = true;
+                        }
+                        // Ticks of the time since the last frame
+                        let now = Instant::now();
+                        let keys = bindings.player_input(&input);
+                        for _ in 0..clock.advance(now - last_frame) {
+                            // The first tick takes the whole motion and the toggles
+                            let toggles = std::mem::take(&mut pending);
+                            let mut player_input = PlayerInput {
+                                mouse: mouse.motion(std::mem::take(&mut mouse_motion)),
+                                toggle_noclip: toggles.toggle_noclip,
+                                toggle_fly: toggles.toggle_fly,
+                                ..keys
+                            };
+                            // The demo drives the player until its end
+                            if let Some(inputs) = playback.as_mut() {
+                                match inputs.next() {
+                                    Some(demo_input) => player_input = demo_input,
+                                    None => {
+                                        println!("Demo finished");
+                                        playback = None;
+                                    }
+                                }
+                            }
+                            if let Some((_, demo)) = recording.as_mut() {
+                                demo.record(&player_input);
+                            }
+                            previous = *player;
+                            player.tick(&render.world, &player_input, clock.delta());
                         }
+                        last_frame = now;
                         // Draw
                         window.request_redraw();
                     }
@@ -1,162 +1,477 @@
 #![allow(dead_code)]
 // Using, d3d
+use crate::bsp::Bsp;
 use crate::math::{Vec2, Vec3};
 use crate::player::Player;
-use crate::world::{Material, TextureMapping, SectorHeight, Sector, Wall, World};
+use crate::texture::TextureSet;
+use crate::wad::{Wad, WadError};
+use crate::world::{Material, TextureMapping, SectorHeight, Sector, Thing, Wall, World, FULL_LIGHT};
 // Usings
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::option::Option;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Lines};
+use std::path::Path;
 use std::rc::Rc;
 use std::cell::RefCell;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapLine {
+    Count,
+    Sector,
+    Wall,
+    Player,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    UnexpectedEof { line: usize, expected: MapLine },
+    BadInteger { line: usize, column: usize, token: String },
+    FieldCount { line: usize, kind: MapLine, found: usize, expected: &'static [usize] },
+    MissingSeparator { line: usize },
+    Unrepresentable { kind: MapLine, index: usize },
+    Things { count: usize },
+    Ron(ron::error::SpannedError),
+    RonWrite(ron::Error),
+    Version { found: u32, supported: u32 },
+    Wad(WadError),
+}
+
+impl fmt::Display for MapLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapLine::Count => write!(f, "count"),
+            MapLine::Sector => write!(f, "sector"),
+            MapLine::Wall => write!(f, "wall"),
+            MapLine::Player => write!(f, "player"),
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(error) => write!(f, "I/O error: {}", error),
+            MapError::UnexpectedEof { line, expected } =>
+                write!(f, "line {}: unexpected end of file, expected a {} line", line, expected),
+            MapError::BadInteger { line, column, token } =>
+                write!(f, "line {}, column {}: {:?} is not a valid integer", line, column, token),
+            MapError::FieldCount { line, kind, found, expected } =>
+                write!(f, "line {}: {} line has {} fields, expected {}", line, kind, found,
+                       expected.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(" or ")),
+            MapError::MissingSeparator { line } =>
+                write!(f, "line {}: expected an empty line before the player", line),
+            MapError::Unrepresentable { kind, index } =>
+                write!(f, "{} {} has materials, a light, a portal or a blend that the map format cannot store", kind, index),
+            MapError::Things { count } =>
+                write!(f, "the map format cannot store things, the map has {}", count),
+            MapError::Ron(error) => write!(f, "{}", error),
+            MapError::RonWrite(error) => write!(f, "{}", error),
+            MapError::Version { found, supported } =>
+                write!(f, "map version {} is newer than the supported version {}", found, supported),
+            MapError::Wad(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapError::Io(error) => Some(error),
+            MapError::Ron(error) => Some(error),
+            MapError::RonWrite(error) => Some(error),
+            MapError::Wad(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(error: io::Error) -> Self {
+        MapError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for MapError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        MapError::Ron(error)
+    }
+}
+
+impl From<ron::Error> for MapError {
+    fn from(error: ron::Error) -> Self {
+        MapError::RonWrite(error)
+    }
+}
+
+impl From<WadError> for MapError {
+    fn from(error: WadError) -> Self {
+        MapError::Wad(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapFormat {
+    // Column format, the meaning of a line depends on its number of fields
+    Legacy,
+    // Keyed and versioned RON format
+    Ron,
+    // Doom levels, read only
+    Wad,
+}
+
+impl MapFormat {
+    pub fn from_path(path: &str) -> MapFormat {
+        match Path::new(path).extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("ron") => MapFormat::Ron,
+            Some(extension) if extension.eq_ignore_ascii_case("wad") => MapFormat::Wad,
+            _ => MapFormat::Legacy,
+        }
+    }
+}
+
+// Current version of the RON format
+pub const MAP_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+// Angles in degrees, integers in the files of the first version read as floats
+struct PlayerStart {
+    position: Vec3<i32>,
+    angle: f32,
+    updown: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MapFile {
+    version: u32,
+    player: PlayerStart,
+    sectors: Vec<Sector>,
+    walls: Vec<Wall>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    things: Vec<Thing>,
+    // Node builder output, rebuilt when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bsp: Option<Bsp>,
+}
+
+// Valid number of fields per line
+const SECTOR_FIELDS: &[usize] = &[4, 6, 7, 10];
+const WALL_FIELDS: &[usize] = &[4, 7, 8];
+const PLAYER_FIELDS: &[usize] = &[5];
+const COUNT_FIELDS: &[usize] = &[1];
+const WHITE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+struct MapReader<R: BufRead> {
+    lines: Lines<R>,
+    line: usize,
+}
+
+impl<R: BufRead> MapReader<R> {
+    fn new(reader: R) -> Self {
+        MapReader { lines: reader.lines(), line: 0 }
+    }
+
+    fn next_line(&mut self, expected: MapLine) -> Result<String, MapError> {
+        match self.lines.next() {
+            Some(line) => {
+                self.line += 1;
+                Ok(line?)
+            }
+            None => Err(MapError::UnexpectedEof { line: self.line + 1, expected }),
+        }
+    }
+
+    fn numbers(&mut self, kind: MapLine, valid: &'static [usize]) -> Result<Vec<i32>, MapError> {
+        let line = self.next_line(kind)?;
+        let mut numbers = Vec::new();
+        let mut rest = line.as_str();
+        let mut column = 1;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            column += start;
+            rest = &rest[start..];
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let token = &rest[..end];
+            match token.parse::<i32>() {
+                Ok(value) => numbers.push(value),
+                Err(_) => return Err(MapError::BadInteger {
+                    line: self.line,
+                    column,
+                    token: String::from(token)
+                }),
+            }
+            column += end;
+            rest = &rest[end..];
+        }
+        if !valid.contains(&numbers.len()) {
+            return Err(MapError::FieldCount { line: self.line, kind, found: numbers.len(), expected: valid });
+        }
+        Ok(numbers)
+    }
+
+    fn count(&mut self) -> Result<usize, MapError> {
+        let numbers = self.numbers(MapLine::Count, COUNT_FIELDS)?;
+        match usize::try_from(numbers[0]) {
+            Ok(count) => Ok(count),
+            Err(_) => Err(MapError::BadInteger { line: self.line, column: 1, token: numbers[0].to_string() }),
+        }
+    }
+}
+
 pub struct Map {
     pub player: Rc<RefCell<Player>>,
     pub world: Rc<World>,
+    pub bsp: Option<Rc<Bsp>>,
 }
 
 impl Map {
-    pub fn from(path: &str) -> Option<Map> {
-        if let Ok(file) = File::open(path) {
-            // Reader buffer
-            let reader = BufReader::new(file);
-            // Get all lines
-            let mut lines = reader.lines();
-            // Sectors
-            let mut sectors: Vec<Sector> = Vec::new();
-            // Read number of sectors
-            let number_of_sectors: i32 = match lines.next() {
-                Some(Ok(line)) => line.trim().parse().unwrap_or(0),
-                _ => 0,
-            };
-            for _ in 0..number_of_sectors {
-                let numbers: Vec<i32> = match lines.next() {
-                    Some(Ok(line)) => line
-                        .split_whitespace()
-                        .map(|s| s.parse().expect("Failed to parse number"))
-                        .collect(),
-                    _ => vec![],
-                };
-                if numbers.len() < 4 {
-                    return None;
-                }                
-                let sector: Sector =  match numbers.len() {
-                    6 => Sector::new_with_material(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &SectorHeight::new(numbers[2], numbers[3]),
+    pub fn from(path: &str) -> Result<Map, MapError> {
+        match MapFormat::from_path(path) {
+            MapFormat::Legacy => Map::from_reader(BufReader::new(File::open(path)?)),
+            MapFormat::Ron => Map::from_ron(&fs::read_to_string(path)?),
+            MapFormat::Wad => Map::from_wad(&Wad::from(path)?, None, None),
+        }
+    }
+
+    pub fn from_wad(wad: &Wad, level: Option<&str>, textures: Option<&TextureSet>) -> Result<Map, MapError> {
+        let level = match level {
+            Some(level) => String::from(level),
+            None => match wad.levels().first() {
+                Some(level) => level.clone(),
+                None => return Err(MapError::Wad(WadError::LevelNotFound(String::from("any")))),
+            },
+        };
+        let (world, player) = wad.load_level(&level, textures)?;
+        Ok(Map {
+            player: Rc::new(RefCell::new(player)),
+            world: Rc::new(world),
+            bsp: None,
+        })
+    }
+
+    pub fn from_ron(text: &str) -> Result<Map, MapError> {
+        let file: MapFile = ron::from_str(text)?;
+        if file.version > MAP_VERSION {
+            return Err(MapError::Version { found: file.version, supported: MAP_VERSION });
+        }
+        let player = Player::new_with_position_angles(
+            file.player.position,
+            file.player.angle,
+            file.player.updown
+        );
+        Ok(Map {
+            player: Rc::new(RefCell::new(player)),
+            world: Rc::new(World {
+                walls: file.walls,
+                sectors: file.sectors,
+                things: file.things,
+            }),
+            bsp: file.bsp.map(Rc::new),
+        })
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Map, MapError> {
+        // Reader buffer
+        let mut reader = MapReader::new(reader);
+        // Sectors
+        let mut sectors: Vec<Sector> = Vec::new();
+        // Read number of sectors
+        let number_of_sectors = reader.count()?;
+        for _ in 0..number_of_sectors {
+            let numbers = reader.numbers(MapLine::Sector, SECTOR_FIELDS)?;
+            let sector: Sector =  match numbers.len() {
+                6 => Sector::new_with_material(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &SectorHeight::new(numbers[2], numbers[3]),
+                    Material::Texture(TextureMapping {
+                        texture: numbers[4] as usize,
+                        uv: Vec2::new(numbers[5], numbers[5]),
+                        shade: 0
+                    })
+                ),
+                7 => Sector::new_with_material(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &SectorHeight::new(numbers[2], numbers[3]),
+                    Material::Color([numbers[4] as u8,numbers[5] as u8,numbers[6] as u8, 0xff])
+                ),
+                10 => Sector::new_with_materials(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &SectorHeight::new(numbers[2], numbers[3]),
+                    [
                         Material::Texture(TextureMapping {
-                            texture: numbers[4] as usize, 
+                            texture: numbers[4] as usize,
                             uv: Vec2::new(numbers[5], numbers[5]),
-                            shade: 0
-                        })
-                    ),
-                    7 => Sector::new_with_material(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &SectorHeight::new(numbers[2], numbers[3]),
-                        Material::Color([numbers[4] as u8,numbers[5] as u8,numbers[6] as u8, 0xff])
-                    ),
-                    10 => Sector::new_with_materials(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &SectorHeight::new(numbers[2], numbers[3]),
-                        [
-                            Material::Texture(TextureMapping {
-                                texture: numbers[4] as usize, 
-                                uv: Vec2::new(numbers[5], numbers[5]),
-                                shade: numbers[6] as u8
-                            }),
-                            Material::Texture(TextureMapping {
-                                texture: numbers[7] as usize, 
-                                uv: Vec2::new(numbers[8], numbers[8]),
-                                shade: numbers[9] as u8
-                            }),
-                        ]
-                    ),
-                    _ => Sector::new(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &SectorHeight::new(numbers[2], numbers[3]),
-                    ),
-                };
-                sectors.push(sector);
-            }
-            // Walls
-            let mut walls: Vec<Wall> = Vec::new();
-            // Number of walls
-            let number_of_walls: i32 = match lines.next() {
-                Some(Ok(line)) => line.trim().parse().unwrap_or(0),
-                _ => 0,
-            };
-            for _ in 0..number_of_walls {
-                let numbers: Vec<i32> = match lines.next() {
-                    Some(Ok(line)) => line
-                        .split_whitespace()
-                        .map(|s| s.parse().expect("Failed to parse number"))
-                        .collect(),
-                    _ => vec![],
-                };
-                if numbers.len() < 4 {
-                    return None;
-                }
-                let wall: Wall =  match numbers.len() {
-                    7 => Wall::new_with_material(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &Vec2::new(numbers[2], numbers[3]),
-                        Material::Color([numbers[4] as u8,numbers[5] as u8,numbers[6] as u8, 0xff])
-                    ),
-                    8 => Wall::new_with_material(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &Vec2::new(numbers[2], numbers[3]),
+                            shade: numbers[6] as u8
+                        }),
                         Material::Texture(TextureMapping {
-                            texture: numbers[4] as usize, 
-                            uv: Vec2::new(numbers[5], numbers[6]),
-                            shade: numbers[7] as u8
-                        })
-                    ),
-                    _ => Wall::new(
-                        &Vec2::new(numbers[0], numbers[1]),
-                        &Vec2::new(numbers[2], numbers[3]),
-                    )
-                };
-                walls.push(wall);
-            }
-            // Build world
-            let world = Rc::new(World {
-                walls: walls,
-                sectors: sectors,
-            });
-
-            // Read void line
-            let void_line = match lines.next() {
-                Some(Ok(line)) => String::from(line.trim()),
-                _ => return None,
+                            texture: numbers[7] as usize,
+                            uv: Vec2::new(numbers[8], numbers[8]),
+                            shade: numbers[9] as u8
+                        }),
+                    ]
+                ),
+                _ => Sector::new(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &SectorHeight::new(numbers[2], numbers[3]),
+                ),
             };
-            if !void_line.is_empty() {
-                return None;
-            }
-
-            // Read player
-            let player_numbers: Vec<i32> = match lines.next() {
-                Some(Ok(line)) => line
-                    .split_whitespace()
-                    .map(|s| s.parse().expect("Failed to parse number"))
-                    .collect(),
-                _ => vec![],
+            sectors.push(sector);
+        }
+        // Walls
+        let mut walls: Vec<Wall> = Vec::new();
+        // Number of walls
+        let number_of_walls = reader.count()?;
+        for _ in 0..number_of_walls {
+            let numbers = reader.numbers(MapLine::Wall, WALL_FIELDS)?;
+            let wall: Wall =  match numbers.len() {
+                7 => Wall::new_with_material(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &Vec2::new(numbers[2], numbers[3]),
+                    Material::Color([numbers[4] as u8,numbers[5] as u8,numbers[6] as u8, 0xff])
+                ),
+                8 => Wall::new_with_material(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &Vec2::new(numbers[2], numbers[3]),
+                    Material::Texture(TextureMapping {
+                        texture: numbers[4] as usize,
+                        uv: Vec2::new(numbers[5], numbers[6]),
+                        shade: numbers[7] as u8
+                    })
+                ),
+                _ => Wall::new(
+                    &Vec2::new(numbers[0], numbers[1]),
+                    &Vec2::new(numbers[2], numbers[3]),
+                )
             };
-            if player_numbers.len() < 5 {
-                return None;
+            walls.push(wall);
+        }
+        // Build world
+        let world = Rc::new(World {
+            walls,
+            sectors,
+            things: vec![],
+        });
+
+        // Read void line
+        if !reader.next_line(MapLine::Player)?.trim().is_empty() {
+            return Err(MapError::MissingSeparator { line: reader.line });
+        }
+
+        // Read player
+        let player_numbers = reader.numbers(MapLine::Player, PLAYER_FIELDS)?;
+        let player = Rc::new(RefCell::new(Player::new_with_position_angles(
+            Vec3::new(player_numbers[0], player_numbers[1], player_numbers[2]),
+            player_numbers[3] as f32,
+            player_numbers[4] as f32
+        )));
+
+        // Return the
+        Ok(Map {
+            player,
+            world,
+            bsp: None,
+        })
+    }
+
+    pub fn build_bsp(&mut self) {
+        self.bsp = Some(Rc::new(Bsp::build(&self.world)));
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), MapError> {
+        let text = match MapFormat::from_path(path) {
+            MapFormat::Ron => self.to_ron()?,
+            _ => self.to_string()?,
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn to_ron(&self) -> Result<String, MapError> {
+        let player = self.player.borrow();
+        let file = MapFile {
+            version: MAP_VERSION,
+            player: PlayerStart {
+                position: player.position,
+                angle: player.angle,
+                updown: player.updown,
+            },
+            sectors: self.world.sectors.clone(),
+            walls: self.world.walls.clone(),
+            things: self.world.things.clone(),
+            bsp: self.bsp.as_deref().cloned(),
+        };
+        let config = ron::ser::PrettyConfig::new().depth_limit(3);
+        Ok(ron::ser::to_string_pretty(&file, config)?)
+    }
+
+    pub fn to_string(&self) -> Result<String, MapError> {
+        if !self.world.things.is_empty() {
+            return Err(MapError::Things { count: self.world.things.len() });
+        }
+        let mut out = String::new();
+        // Sectors
+        writeln!(out, "{}", self.world.sectors.len()).unwrap();
+        for (index, sector) in self.world.sectors.iter().enumerate() {
+            if sector.light != FULL_LIGHT {
+                return Err(MapError::Unrepresentable { kind: MapLine::Sector, index });
+            }
+            let wall = sector.wall;
+            let height = sector.height;
+            write!(out, "{} {} {} {}", wall.x, wall.y, height.top, height.bottom).unwrap();
+            match sector.material {
+                // 4 columns, default white
+                [Material::Color(WHITE), Material::Color(WHITE)] => {},
+                // 7 columns, one color
+                [Material::Color(top), Material::Color(bottom)]
+                if top == bottom && top[3] == 0xff => {
+                    write!(out, " {} {} {}", top[0], top[1], top[2]).unwrap();
+                },
+                // 6 columns, one texture
+                [Material::Texture(top), Material::Texture(bottom)]
+                if top == bottom && top.uv.x == top.uv.y && top.shade == 0 => {
+                    write!(out, " {} {}", top.texture, top.uv.x).unwrap();
+                },
+                // 10 columns, two textures
+                [Material::Texture(top), Material::Texture(bottom)]
+                if top.uv.x == top.uv.y && bottom.uv.x == bottom.uv.y => {
+                    write!(out, " {} {} {} {} {} {}",
+                           top.texture, top.uv.x, top.shade,
+                           bottom.texture, bottom.uv.x, bottom.shade).unwrap();
+                },
+                _ => return Err(MapError::Unrepresentable { kind: MapLine::Sector, index }),
+            }
+            out.push('\n');
+        }
+        // Walls
+        writeln!(out, "{}", self.world.walls.len()).unwrap();
+        for (index, wall) in self.world.walls.iter().enumerate() {
+            if wall.portal.is_some() || !wall.blend.is_opaque() || wall.middle.is_some() || wall.lower.is_some() {
+                return Err(MapError::Unrepresentable { kind: MapLine::Wall, index });
+            }
+            write!(out, "{} {} {} {}", wall.point1.x, wall.point1.y, wall.point2.x, wall.point2.y).unwrap();
+            match wall.material {
+                // 4 columns, default white
+                Material::Color(WHITE) => {},
+                // 7 columns, color
+                Material::Color(color) if color[3] == 0xff => {
+                    write!(out, " {} {} {}", color[0], color[1], color[2]).unwrap();
+                },
+                // 8 columns, texture
+                Material::Texture(map) => {
+                    write!(out, " {} {} {} {}", map.texture, map.uv.x, map.uv.y, map.shade).unwrap();
+                },
+                _ => return Err(MapError::Unrepresentable { kind: MapLine::Wall, index }),
             }
-            let player = Rc::new(RefCell::new(Player::new_with_position_angles(
-                Vec3::new(player_numbers[0], player_numbers[1], player_numbers[2]),
-                player_numbers[3],
-                player_numbers[4]
-            )));
-
-            // Return the
-            return Some(Map {
-                player: player,
-                world: world,
-            });
-        } else {
-            return None;
+            out.push('\n');
         }
+        // Void line
+        out.push('\n');
+        // Player
+        let player = self.player.borrow();
+        writeln!(out, "{} {} {} {} {}",
+                 player.position.x, player.position.y, player.position.z,
+                 player.angle.round(), player.updown.round()).unwrap();
+        Ok(out)
     }
 }
@@ -4,8 +4,9 @@ use core::ops;
 use lazy_static::lazy_static;
 use std::f32::consts::PI;
 use num_traits::{cast::NumCast, Float};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub struct Vec2<T> {
     pub x: T,
     pub y: T,
@@ -124,7 +125,7 @@ impl<T: ops::Sub<Output = T> + Sized + Copy + NumCast> ops::SubAssign<Vec2<T>> f
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Vec3<T> {
     pub x: T,
     pub y: T,
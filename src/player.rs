@@ -1,168 +1,217 @@
 #![allow(dead_code)]
 // Using, d3d
-use crate::math::{Vec3, self};
-use crate::consts::{MOVE_VELOCITY, ROTATION_VELOCITY, UPDOWN_VELOCITY};
-// Using
-use winit::event::{
-    Event, VirtualKeyCode
+use crate::math::{Vec2, Vec3};
+use crate::consts::{
+    EYE_HEIGHT, GRAVITY, MAX_UPDOWN, MOUSE_SENSITIVITY, MOVE_VELOCITY, PLAYER_HEIGHT, PLAYER_RADIUS,
+    ROTATION_VELOCITY, STEP_HEIGHT, UPDOWN_VELOCITY
 };
-use winit_input_helper::WinitInputHelper;
+use crate::world::{Wall, World};
+// Using
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Player {
         float_position: Vec3<f32>,
+        fall_velocity: f32,
     pub position: Vec3<i32>,
-    pub angle: i32,
-    pub updown: i32
+    // Degrees clockwise from north
+    pub angle: f32,
+    pub updown: f32,
+    // Walks through the walls
+    pub noclip: bool,
+    // Moves up and down freely instead of standing on the floor
+    pub flying: bool
+}
+
+// Actions held during a tick, each axis is -1, 0 or 1, the mouse motion since
+// the last tick as degrees of turn and look, and the toggles pressed. Forward and
+// strafe move along the view, north and east along the map axes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub forward: i8,
+    pub strafe: i8,
+    pub north: i8,
+    pub east: i8,
+    pub fly: i8,
+    pub turn: i8,
+    pub look: i8,
+    pub mouse: Vec2<f32>,
+    pub toggle_noclip: bool,
+    pub toggle_fly: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseSettings {
+    // Degrees for each unit of mouse motion
+    pub sensitivity: f32,
+    // Moving the mouse up looks down
+    pub invert_y: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        MouseSettings {
+            sensitivity: MOUSE_SENSITIVITY,
+            invert_y: false,
+        }
+    }
+}
+
+impl MouseSettings {
+    // Raw motion of the device to turn and look of the player
+    pub fn motion(&self, delta: (f64, f64)) -> Vec2<f32> {
+        let look = if self.invert_y { -delta.1 } else { delta.1 };
+        Vec2::new(delta.0 as f32, look as f32) * self.sensitivity
+    }
 }
 
 impl Player {
     pub fn new() -> Self {
-        Player {
-            float_position: Vec3::new(0.0,0.0,0.0),
-            position: Vec3::new(0,0,0),
-            angle: 0,
-            updown: 0
-        }
+        Player::new_with_position(Vec3::new(0,0,0))
     }
     
     pub fn new_with_position(initial_position: Vec3<i32>) -> Self {
-        Player {
-            float_position: Vec3::new(initial_position.x as f32,initial_position.y as f32,initial_position.z as f32),
-            position: initial_position,
-            angle: 0,
-            updown: 0
-        }
+        Player::new_with_position_angles(initial_position, 0.0, 0.0)
     }
     
-    pub fn new_with_position_angles(initial_position: Vec3<i32>, angle: i32, updown: i32) -> Self {
+    pub fn new_with_position_angles(initial_position: Vec3<i32>, angle: f32, updown: f32) -> Self {
         Player {
             float_position: Vec3::new(initial_position.x as f32,initial_position.y as f32,initial_position.z as f32),
+            fall_velocity: 0.0,
             position: initial_position,
-            angle: angle,
-            updown: updown
+            angle,
+            updown,
+            noclip: false,
+            flying: false
         }
     }
 
-    pub fn right(&mut self) {
-        self.angle = (self.angle + ROTATION_VELOCITY) % 360;
-    }
-
-    pub fn left(&mut self) {
-        self.angle = ((self.angle - ROTATION_VELOCITY) + 360) % 360;
+    // Degrees clockwise
+    pub fn turn(&mut self, degrees: f32) {
+        self.angle = (self.angle + degrees).rem_euclid(360.0);
+        // Rounding of a small negative angle
+        if self.angle >= 360.0 {
+            self.angle = 0.0;
+        }
     }
 
+    // Same values of the math::COS and math::SIN tables on whole degrees
     pub fn cos(&self) -> f32 {
-        math::COS[self.angle as usize]
+        (self.angle * PI / 180.0).cos()
     }
 
     pub fn sin(&self) -> f32 {
-        math::SIN[self.angle as usize]
+        (self.angle * PI / 180.0).sin()
     }
 
-    pub fn up(&mut self) {
-        self.updown -= UPDOWN_VELOCITY;
+    // Positive looks down
+    pub fn look(&mut self, amount: f32) {
+        self.updown = (self.updown + amount).clamp(-MAX_UPDOWN, MAX_UPDOWN);
     }
 
-    pub fn down(&mut self) {
-        self.updown += UPDOWN_VELOCITY;
+    pub fn dirmove(&mut self, xydir: Vec3<f32>) {
+        self.translate(self.view_direction(xydir));
     }
 
-    pub fn dirmove(&mut self, xydir: Vec3<f32>) {
-        let x = self.cos() * (xydir.x as f32) + self.sin() * (xydir.y as f32);
-        let y =-self.sin() * (xydir.x as f32) + self.cos() * (xydir.y as f32);
-        self.translate(Vec3::new(x,y, xydir.z as f32));
+    // Direction relative to the view, in the map
+    fn view_direction(&self, xydir: Vec3<f32>) -> Vec3<f32> {
+        let x = self.cos() * xydir.x + self.sin() * xydir.y;
+        let y =-self.sin() * xydir.x + self.cos() * xydir.y;
+        Vec3::new(x,y, xydir.z)
+    }
+
+    // Moves on the map sliding along the walls, through them with noclip
+    pub fn walk(&mut self, world: &World, direction: Vec2<f32>) {
+        let from = self.float_position.xy();
+        let to = if self.noclip {
+            from + direction
+        } else {
+            world.slide(&from, &direction, PLAYER_RADIUS, |wall| self.blocked_by(world, wall))
+        };
+        self.translate(Vec3::new(to.x - from.x, to.y - from.y, 0.0));
+    }
+
+    pub fn feet(&self) -> f32 {
+        self.float_position.z - EYE_HEIGHT
+    }
+
+    // Solid walls, and portals to a floor higher than a step or to a ceiling too low for the body
+    fn blocked_by(&self, world: &World, wall: &Wall) -> bool {
+        let sector = match wall.portal.and_then(|sector| world.sectors.get(sector)) {
+            Some(sector) => sector,
+            None => return true,
+        };
+        let step = if self.flying { 0.0 } else { STEP_HEIGHT };
+        let (bottom, top) = (sector.height.bottom as f32, sector.height.top as f32);
+        let feet = self.feet();
+        feet + step < bottom || top < feet.max(bottom) + PLAYER_HEIGHT
+    }
+
+    // Stands on the floor of the sector below, stepping up to it or falling down to it
+    fn fall(&mut self, world: &World, dt: f32) {
+        let floor = match world.sector_at(&self.float_position.xy()) {
+            Some(sector) => world.sectors[sector].height.bottom as f32,
+            None => return,
+        };
+        let feet = self.feet();
+        if feet <= floor {
+            self.fall_velocity = 0.0;
+            self.translate(Vec3::new_z(floor - feet));
+            return;
+        }
+        self.fall_velocity += GRAVITY * dt;
+        let drop = self.fall_velocity * dt;
+        if feet - floor <= drop {
+            // Landed
+            self.fall_velocity = 0.0;
+            self.translate(Vec3::new_z(floor - feet));
+        } else {
+            self.translate(Vec3::new_z(-drop));
+        }
     }
 
     pub fn translate(&mut self, direction: Vec3<f32>) {
         self.float_position += direction;
         self.position   = Vec3::new( self.float_position.x as i32, self.float_position.y as i32, self.float_position.z as i32);
     }
-    
-    pub fn execute_input_standard(&mut self, _event: &Event<'_, ()>,input: &WinitInputHelper) {
-        if  input.key_held(VirtualKeyCode::W) 
-        && !input.key_held(VirtualKeyCode::S) {
-            self.translate(Vec3::new_y(MOVE_VELOCITY as f32));
-        }
-        if !input.key_held(VirtualKeyCode::W) 
-        &&  input.key_held(VirtualKeyCode::S) {
-            self.translate(Vec3::new_y(-MOVE_VELOCITY as f32));
-        }        
-        if  input.key_held(VirtualKeyCode::A) 
-        && !input.key_held(VirtualKeyCode::D) {
-            self.translate(Vec3::new_x(-MOVE_VELOCITY as f32));
-        }
-        if !input.key_held(VirtualKeyCode::A) 
-        &&  input.key_held(VirtualKeyCode::D) {
-            self.translate(Vec3::new_x(MOVE_VELOCITY as f32));
-        }
-        if  input.key_held(VirtualKeyCode::R) 
-        && !input.key_held(VirtualKeyCode::F) {
-            self.translate(Vec3::new_z(MOVE_VELOCITY as f32));
-        }
-        if !input.key_held(VirtualKeyCode::R) 
-        &&  input.key_held(VirtualKeyCode::F) {
-            self.translate(Vec3::new_z(-MOVE_VELOCITY as f32));
-        }
-        if  input.key_held(VirtualKeyCode::Left) 
-        && !input.key_held(VirtualKeyCode::Right) {
-            self.left();
-        }
-        if !input.key_held(VirtualKeyCode::Left) 
-        &&  input.key_held(VirtualKeyCode::Right) {
-            self.right();
-        }
-        if  input.key_held(VirtualKeyCode::Up) 
-        && !input.key_held(VirtualKeyCode::Down) {
-            self.up();
-        }
-        if !input.key_held(VirtualKeyCode::Up) 
-        &&  input.key_held(VirtualKeyCode::Down) {
-            self.down();
-        }
+
+    // One step of the simulation, dt seconds long
+    pub fn tick(&mut self, world: &World, input: &PlayerInput, dt: f32) {
+        self.noclip ^= input.toggle_noclip;
+        self.flying ^= input.toggle_fly;
+        let step = MOVE_VELOCITY * dt;
+        let view = self.view_direction(Vec3::new(input.strafe as f32 * step, input.forward as f32 * step, 0.0));
+        let walk = view.xy() + Vec2::new(input.east as f32 * step, input.north as f32 * step);
+        if walk != Vec2::zeros() {
+            self.walk(world, walk);
+        }
+        // Up and down keys take off
+        if input.fly != 0 {
+            self.flying = true;
+            self.fall_velocity = 0.0;
+            self.translate(Vec3::new_z(input.fly as f32 * step));
+        }
+        if !self.flying {
+            self.fall(world, dt);
+        }
+        if input.turn != 0 {
+            self.turn(input.turn as f32 * ROTATION_VELOCITY * dt);
+        }
+        if input.look != 0 {
+            self.look(input.look as f32 * UPDOWN_VELOCITY * dt);
+        }
+        self.turn(input.mouse.x);
+        self.look(input.mouse.y);
     }
 
-    pub fn execute_input_classic(&mut self, _event: &Event<'_, ()>,input: &WinitInputHelper) {
-        if  input.key_held(VirtualKeyCode::W) 
-        && !input.key_held(VirtualKeyCode::S) {
-            self.dirmove(Vec3::new_y(MOVE_VELOCITY as f32));
-        }
-        if !input.key_held(VirtualKeyCode::W) 
-        &&  input.key_held(VirtualKeyCode::S) {
-            self.dirmove(Vec3::new_y(-MOVE_VELOCITY as f32));
-        }        
-        if  input.key_held(VirtualKeyCode::A) 
-        && !input.key_held(VirtualKeyCode::D) {
-            self.dirmove(Vec3::new_x(-MOVE_VELOCITY as f32));
-        }
-        if !input.key_held(VirtualKeyCode::A) 
-        &&  input.key_held(VirtualKeyCode::D) {
-            self.dirmove(Vec3::new_x(MOVE_VELOCITY as f32));
-        }
-        if  input.key_held(VirtualKeyCode::R) 
-        && !input.key_held(VirtualKeyCode::F) {
-            self.translate(Vec3::new_z(MOVE_VELOCITY as f32));
-        }
-        if !input.key_held(VirtualKeyCode::R) 
-        &&  input.key_held(VirtualKeyCode::F) {
-            self.translate(Vec3::new_z(-MOVE_VELOCITY as f32));
-        }
-        if  input.key_held(VirtualKeyCode::Left) 
-        && !input.key_held(VirtualKeyCode::Right) {
-            self.left();
-        }
-        if !input.key_held(VirtualKeyCode::Left) 
-        &&  input.key_held(VirtualKeyCode::Right) {
-            self.right();
-        }
-        if  input.key_held(VirtualKeyCode::Up) 
-        && !input.key_held(VirtualKeyCode::Down) {
-            self.up();
-        }
-        if !input.key_held(VirtualKeyCode::Up) 
-        &&  input.key_held(VirtualKeyCode::Down) {
-            self.down();
-        }
+    // Camera between this tick and the next one, alpha from 0 to 1
+    pub fn interpolate(&self, next: &Player, alpha: f32) -> Player {
+        let mut player = *self;
+        player.translate((next.float_position - self.float_position) * alpha);
+        // Shortest way around
+        player.turn(((next.angle - self.angle + 540.0) % 360.0 - 180.0) * alpha);
+        player.look((next.updown - self.updown) * alpha);
+        player
     }
 }
-
@@ -1,15 +1,29 @@
 #![allow(dead_code)]
 // Using, d3d
 use crate::consts;
-use crate::math::{clamp, lerp, Vec2, Vec3};
+use crate::math::{clamp, lerp, radians, Vec2, Vec3};
 use crate::player::Player;
-use crate::windows::draw_pixel;
-use crate::world::{World, Sector, Material, TextureMapping, SectorHeight};
+use crate::framebuffer::{ColumnWriter, FrameBuffer};
+use crate::world::{World, Sector, Wall, Blend, Material, TextureMapping, Thing, FULL_LIGHT};
+use crate::bsp::{Bsp, Seg};
 use crate::texture::TextureSet;
 // Using
 use std::rc::Rc;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::thread;
 use num_traits::Zero;
-use pixels::Pixels;
+use libm::atanf;
+use std::f32::consts::PI;
+
+// Nearest depth drawn by the portal renderer
+const NEAR_PLANE: f32 = 1.0;
+// Upper bound of portal windows drawn in a frame
+const MAX_PORTAL_WINDOWS: usize = 4096;
+// Rows of Doom's COLORMAP, from full bright to dark
+const LIGHT_LEVELS: i32 = 32;
+// A sector seen at a depth is LIGHT_DISTANCE / depth levels brighter than its light
+const LIGHT_DISTANCE: f32 = 512.0;
 
 #[derive(Clone, Copy, PartialEq)]
 enum Face {
@@ -29,6 +43,9 @@ struct Surface {
     points: Vec<i32>,
     view: SurfaceView,
     wall_offset: i32,
+    // Edges of the front faces per column, nearest first, pushed before drawing by the BSP painter
+    stacked: bool,
+    near_edges: Vec<Vec<i32>>,
 }
 
 #[derive(Clone)]
@@ -36,10 +53,16 @@ struct WallContext {
     wall: [Vec3<i32>; 4],
     depth: [f32; 2],
     uclip: [f32; 2],
+    // Range of the wall the seg covers
+    urange: Vec2<f32>,
     width: f32,
     face: Face,
+    blend: Blend,
+    light: u8,
     distance: i32,
-    visiable: bool
+    visiable: bool,
+    // Columns drawn, the band of the frame
+    columns: Range<i32>,
 }
 
 #[derive(Clone)]
@@ -49,105 +72,344 @@ struct SectorContext {
     distance: i32,
 }
 
+// Player point of view, the same space used by WallContext::project
+struct Camera {
+    player: Player,
+    config: RenderConfig,
+    cos: f32,
+    sin: f32,
+}
+
+// Nearest wall seen by a column
+#[derive(Clone, Copy)]
+struct ColumnHit {
+    x: usize,
+    wall: usize,
+    t: f32,
+    depth: f32,
+}
+
+// Columns of a sector seen through a portal
+struct PortalWindow {
+    sector: usize,
+    x1: usize,
+    x2: usize,
+    entry: Vec<f32>,
+    wall: Option<usize>,
+}
+
+// Portal crossed by a column, what is behind it is seen only from bottom to top
+#[derive(Clone, Copy)]
+struct PortalClip {
+    depth: f32,
+    bottom: i32,
+    top: i32,
+}
+
+// Rows of a column painted by a wall of the painter's algorithm
+#[derive(Clone, Copy)]
+struct PaintedWall {
+    depth: f32,
+    bottom: i32,
+    top: i32,
+}
+
+// Depth of the solid wall of each column and the portals in front of it,
+// sprites are drawn only where nothing nearer covers them
+#[derive(Clone)]
+struct DepthBuffer {
+    walls: Vec<f32>,
+    portals: Vec<Vec<PortalClip>>,
+    // Walls of the painter in the order they are drawn, each covers only its rows
+    painted: Vec<Vec<PaintedWall>>,
+}
+
+// Opening of a portal that is not opaque, drawn back to front with the sprites
+#[derive(Clone, Copy)]
+struct MaskedColumn {
+    hit: ColumnHit,
+    rows: (i32, i32),
+    edges: (f32, f32),
+    light: u8,
+}
+
+// Thing on screen, from left to right and from y_low to y_high
+struct Sprite {
+    thing: usize,
+    depth: f32,
+    left: f32,
+    right: f32,
+    y_low: f32,
+    y_high: f32,
+    // Brightness of the sector the thing stands in, at its depth
+    brightness: u8,
+}
+
+#[derive(Clone)]
+struct PortalContext {
+    bottom: Vec<i32>,
+    top: Vec<i32>,
+    hits: Vec<Option<ColumnHit>>,
+    // Sector seen by each column and the last portal crossed, used by the BSP walk
+    sectors: Vec<usize>,
+    crossed: Vec<Option<usize>>,
+    // What hides the sprites, filled by the walls of any render mode
+    depth: DepthBuffer,
+    masked: Vec<MaskedColumn>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderMode {
+    // Sectors painted back to front
+    Sectors,
+    // From the player sector through its portals
+    Portals,
+    // BSP leaves front to back, until the screen is filled
+    Bsp,
+}
+
+// Shared by the threads drawing a frame
+struct Scene<'a> {
+    world: &'a World,
+    textures: &'a TextureSet,
+    bsp: Option<&'a Bsp>,
+    config: &'a RenderConfig,
+    mode: RenderMode,
+    player: &'a Player,
+    // Sector of the player, when inside of one
+    sector: Option<usize>,
+}
+
+// Columns of the frame drawn by one thread, with their own clip arrays
+struct ColumnsContext {
+    columns: Range<usize>,
+    sectors_context: Vec<SectorContext>,
+    portal_context: PortalContext,
+    sprites: Vec<Sprite>,
+}
+
 pub struct Render {
     pub world: Rc<World>,
     pub textures: Rc<TextureSet>,
-    sectors_context: Vec<SectorContext>
+    pub config: RenderConfig,
+    pub mode: RenderMode,
+    bsp: Option<Rc<Bsp>>,
+    bands: Vec<ColumnsContext>,
 }
 
-mod render {
-    use lazy_static::lazy_static;
+// Size of the frame, in pixels, and camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderConfig {
+    pub width: u32,
+    pub height: u32,
+    // Window pixels for each frame pixel
+    pub scale: u32,
+    // Horizontal field of view in degrees
+    pub fov: f32,
+    // Keep the vertical view of a 4:3 frame, wider frames see more on the sides
+    pub aspect_correct: bool,
+    // Bands of columns drawn in parallel
+    pub threads: usize,
+}
 
-    use crate::consts::{self, H_WIDTH, H_FOV};
-    use crate::math::radians;
-    use std::f32::consts::PI;
-    use libm::atanf;
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            width: consts::WIDTH,
+            height: consts::HEIGHT,
+            scale: consts::SCREEN_RESOLUTION,
+            fov: consts::FOV,
+            aspect_correct: false,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+}
 
-    lazy_static! {
-        pub static ref SCREEN_DIST: f32 = {
-            (consts::H_WIDTH as f32) / radians(H_FOV).tan()
-        };
+impl RenderConfig {
+    pub fn new(width: u32, height: u32, scale: u32) -> Self {
+        RenderConfig { width, height, scale, ..RenderConfig::default() }
+    }
 
-        pub static ref X_TO_ANGLE: [f32; (consts::WIDTH+1) as usize] = {
-            let mut x_to_angle = [0.0; (consts::WIDTH+1) as usize];
-            for x in 0..=consts::WIDTH {
-                x_to_angle[x as usize] = atanf((consts::H_WIDTH - x) as f32 / *SCREEN_DIST);
-            }
-            x_to_angle
-        };
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = clamp(fov, consts::MIN_FOV, consts::MAX_FOV);
+    }
+
+    // Field of view across the whole width, larger than fov on wide aspect correct frames
+    pub fn horizontal_fov(&self) -> f32 {
+        2.0 * atanf(self.half_width() as f32 / self.screen_dist()) * 180.0 / PI
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        (self.width * self.scale, self.height * self.scale)
     }
 
     #[inline]
-    pub fn inv_fov() -> f32 {
-        1.0 / (consts::H_FOV * PI / 180.0).tan()
+    pub fn half_width(&self) -> i32 {
+        (self.width / 2) as i32
     }
 
     #[inline]
-    pub fn width_on_fov() -> i32 {
-        ((consts::WIDTH as f32) * inv_fov()) as i32
-    } 
+    pub fn half_height(&self) -> i32 {
+        (self.height / 2) as i32
+    }
 
-    pub fn angle_to_x(angle: f32) -> f32 {
+    // Frame size against the 160x120 the textures of the planes are tiled for
+    #[inline]
+    pub fn resolution(&self) -> f32 {
+        (self.height * consts::RESOLUTION) as f32 / consts::HEIGHT as f32
+    }
+
+    #[inline]
+    pub fn inv_fov(&self) -> f32 {
+        1.0 / (self.fov / 2.0 * PI / 180.0).tan()
+    }
+
+    // Width the fov spans, the frame one or the 4:3 one of its height
+    #[inline]
+    pub fn fov_width(&self) -> f32 {
+        if self.aspect_correct {
+            self.height as f32 * consts::WIDTH as f32 / consts::HEIGHT as f32
+        } else {
+            self.width as f32
+        }
+    }
+
+    // Focal length in pixels, of both x and y
+    #[inline]
+    pub fn width_on_fov(&self) -> i32 {
+        (self.fov_width() * self.inv_fov()) as i32
+    }
+
+    pub fn screen_dist(&self) -> f32 {
+        (self.fov_width() / 2.0) / radians(self.fov / 2.0).tan()
+    }
+
+    pub fn x_to_angle(&self, x: i32) -> f32 {
+        atanf((self.half_width() - x) as f32 / self.screen_dist())
+    }
+
+    pub fn angle_to_x(&self, angle: f32) -> f32 {
         if angle > 0.0 {
-            *SCREEN_DIST - angle.tan() * (H_WIDTH as f32)
+            self.screen_dist() - angle.tan() * (self.half_width() as f32)
         } else {
-            -angle.tan() * (H_WIDTH as f32) + *SCREEN_DIST
+            -angle.tan() * (self.half_width() as f32) + self.screen_dist()
         }
     }
 }
 
-impl Surface {
+fn look_and_move_updown(player: &Player, config: &RenderConfig, plane_height: i32) -> (f32, f32) {
+    // Looks up and down factor
+    let factor = (config.width_on_fov() as f32) / consts::UPDOWN_FACTOR - 0.1;
+    // Start
+    let look_updown = -player.updown * factor;    
+    // Move
+    let mut move_updown = (player.position.z - plane_height) as f32 / (config.half_height() as f32); 
+    if move_updown == 0.0 { move_updown = 0.001; }
+    // Return 
+    (look_updown, move_updown)
+}
 
-    fn look_and_move_updown(&self, player: &Player) -> (f32, f32) {
-        // Looks up and down factor
-        let factor = (render::width_on_fov() as f32) / (consts::UPDOWN_FACTOR as f32) - 0.1;
-        // Start
-        let look_updown = -(player.updown as f32) * factor;    
-        // Move
-        let mut move_updown = (player.position.z - self.wall_offset) as f32 / (consts::H_HEIGHT as f32); 
-        if move_updown == 0.0 { move_updown = 0.001; }
-        // Return 
-        return (look_updown, move_updown);
-    }
-
-    fn plane_uv(&self, player: &Player, mut x:i32 , mut y:i32 , look_updown: f32, move_updown: f32, tile: f32) -> (f32, f32) {
-        let xo = consts::H_WIDTH as i32;
-        let yo = consts::H_HEIGHT as i32;
-        x -= xo;
-        y -= yo;
-        let mut z = y as f32 + look_updown; if z == 0.0 { z = 0.0001; }
-        let fx = (x as f32) / z * move_updown * tile;
-        let fy = (render::width_on_fov() as f32) / z * move_updown * tile;
-        let psin = player.sin();
-        let pcos = player.cos();
-        let mut rx = fx * psin - fy * pcos + ((player.position.y as f32)/(yo as f32) * tile); 
-        let mut ry: f32 = fx * pcos + fy * psin - ((player.position.x as f32)/(yo as f32) * tile); 
-        if rx < 0.0 { rx=-rx+1.0; }
-        if ry < 0.0 { ry=-ry+1.0; }
-        return (rx,ry);
+fn plane_uv(player: &Player, config: &RenderConfig, mut x:i32 , mut y:i32 , look_updown: f32, move_updown: f32, tile: f32) -> (f32, f32) {
+    let xo = config.half_width();
+    let yo = config.half_height();
+    x -= xo;
+    y -= yo;
+    let mut z = y as f32 + look_updown; if z == 0.0 { z = 0.0001; }
+    let fx = (x as f32) / z * move_updown * tile;
+    let fy = (config.width_on_fov() as f32) / z * move_updown * tile;
+    let psin = player.sin();
+    let pcos = player.cos();
+    let mut rx = fx * psin - fy * pcos + ((player.position.y as f32)/(yo as f32) * tile); 
+    let mut ry: f32 = fx * pcos + fy * psin - ((player.position.x as f32)/(yo as f32) * tile); 
+    if rx < 0.0 { rx=-rx+1.0; }
+    if ry < 0.0 { ry=-ry+1.0; }
+    (rx,ry)
+}
+
+// Depth of the plane seen at the row y, the point plane_uv finds
+fn plane_depth(config: &RenderConfig, y: i32, look_updown: f32, move_updown: f32) -> f32 {
+    let mut z = (y - config.half_height()) as f32 + look_updown; if z == 0.0 { z = 0.0001; }
+    (move_updown * (config.half_height() * config.width_on_fov()) as f32 / z).abs()
+}
+
+// Brightness of a sector light seen at a depth, as the COLORMAP row Doom picks:
+// dark sectors get lighter up close, full light stays full at any distance
+fn diminished_light(light: u8, depth: f32) -> u8 {
+    let start = (FULL_LIGHT - light) as i32 * LIGHT_LEVELS * 2 / 0x100;
+    let level = (start - (LIGHT_DISTANCE / depth.max(NEAR_PLANE)) as i32).clamp(0, LIGHT_LEVELS - 1);
+    ((LIGHT_LEVELS - level) * 0xff / LIGHT_LEVELS) as u8
+}
+
+// Color darkened by a brightness, the alpha is kept
+fn lit(color: [u8; 4], brightness: u8) -> [u8; 4] {
+    if brightness == 0xff { return color; }
+    let scale = |channel: u8| ((channel as u32 * brightness as u32 + 0x7f) / 0xff) as u8;
+    [scale(color[0]), scale(color[1]), scale(color[2]), color[3]]
+}
+
+fn plane_tile(textures: &TextureSet, config: &RenderConfig, material: &Material) -> f32 {
+    match material {
+         Material::Texture(map) => {
+            map.uv.x as f32 
+            * textures.set[map.texture].dimensions.x as f32 
+            * config.resolution() 
+            * consts::PLANE_TILE_FACTOR
+        },
+         _ => 1.0
     }
+}
+
+fn material_color(textures: &TextureSet, material: &Material, u: f32, v: f32) -> [u8; 4] {
+    match material {
+        Material::Texture(map) => textures.set[map.texture].uv_pixel_shade(u, v, map.shade),
+        Material::Color(color) => *color
+    }
+}
+
+// Column written as the blend of its wall asks
+fn blend_column<F: FnMut(usize) -> [u8; 4]>(frame: &mut ColumnWriter, blend: Blend, x: usize, rows: Range<usize>, mut color: F) {
+    match blend {
+        Blend::Opaque => frame.column(x, rows, color),
+        Blend::Masked => frame.masked_column(x, rows, color),
+        Blend::Translucent(opacity) => frame.blended_column(x, rows, |y| {
+            let mut color = color(y);
+            color[3] = (color[3] as u32 * opacity as u32 / 0xff) as u8;
+            color
+        }),
+    }
+}
+
+impl Surface {
 
     pub fn draw<'a>(
         &'a mut self, 
-        mut pixels: &mut Pixels,
-        player: &Player,
+        frame: &mut ColumnWriter,
+        camera: &Camera,
         face: &Face, 
         x: i32, 
         u: f32,
-        mut y1: i32, 
-        mut y2: i32, 
+        y1: i32, 
+        y2: i32, 
         mut v: f32,
         vs: f32,
         textures: &TextureSet,
-        materials: &[&Material; 3]
+        materials: &[&Material; 3],
+        blend: Blend,
+        (light, depth): (u8, f32)
     ) {
         // Material
-        let mut material = materials[0];
+        let material = materials[0];
         // Surface
         match face {
             Face::Back => {
+                // The farthest front edge not paired yet when stacked
+                let near = if self.stacked {
+                    self.near_edges[x as usize].pop().unwrap_or(self.points[x as usize])
+                } else {
+                    self.points[x as usize]
+                };
                 // Cases
-                match self.view {
+                let (rows, material) = match self.view {
                     SurfaceView::Bottom => { 
                         // Example of a bottom view:
                         //       __
@@ -160,10 +422,9 @@ impl Surface {
                         //     \++++/
                         //      \++/
                         
-                        // Set y2 as the value of the vertical coordinates of the top of the bottom edges,
+                        // Up to the top of the bottom edges,
                         // which are the bottom edges of the front view.
-                        y2 = self.points[x as usize];
-                        material = materials[2];
+                        (y1..near, materials[2])
                     },
                     SurfaceView::Top => { 
                         // Example of a top view:
@@ -177,165 +438,172 @@ impl Surface {
                         //    \||||||/
                         //     \||||/
                         
-                        // Set y1 as the value of the vertical coordinates of the bottom of the top edges,
+                        // From the bottom of the top edges,
                         // which are the top edges of the front view.
-                        y1 = self.points[x as usize]; 
-                        material = materials[1];
+                        (near..y2, materials[1])
                     },
                     SurfaceView::Mid => {
                         return;
                     },
-                }
-                // tiling
-                let tile = match material {
-                     Material::Texture(map) => {
-                        map.uv.x as f32 
-                        * textures.set[map.texture].dimensions.x as f32 
-                        * (consts::RESOLUTION as f32) 
-                        * consts::PLANE_TILE_FACTOR
-                    },
-                     _ => 1.0
                 };
-                // Get look updown
-                let (look_updown, move_updown) = self.look_and_move_updown(&player);
-                // Draw
-                for y in y1..y2 {
-                    // Plane uv
-                    let (pu, pv) = self.plane_uv(&player, x, y, look_updown, move_updown, tile);
-                    // Get color
-                    let colors = match material {
-                        Material::Texture(map) => {
-                            let colors_slice = &textures.set[map.texture].uv_pixel_shade(pu, pv, map.shade);
-                            [colors_slice[0],colors_slice[1],colors_slice[2],colors_slice[3]]
-                        },
-                        Material::Color(color) => *color
-                    };
-                    draw_pixel(&mut pixels, &Vec2::new(x as usize, y as usize), &colors);
-                }
+                draw_plane(frame, camera, textures, (material, self.wall_offset), light, x as usize, rows);
             },
             Face::Front => {
                 // Cases
-                match self.view {
-                    SurfaceView::Bottom => { self.points[x as usize] = y1; }, // save bottom edge of front
-                    SurfaceView::Top    => { self.points[x as usize] = y2; }, // save top edge of front
-                    SurfaceView::Mid    => {  },
+                match (self.stacked, self.view) {
+                    (false, SurfaceView::Bottom) => { self.points[x as usize] = y1; }, // save bottom edge of front
+                    (false, SurfaceView::Top)    => { self.points[x as usize] = y2; }, // save top edge of front
+                    _ => {  },
                 }
-                for y in y1..y2 {
-                    let colors = match material {
-                        Material::Texture(map) => {
-                            let colors_slice = &textures.set[map.texture].uv_pixel_shade(u, v, map.shade);
-                            [colors_slice[0],colors_slice[1],colors_slice[2],colors_slice[3]]
-                        },
-                        Material::Color(color) => *color
-                    };
-                    draw_pixel(&mut pixels, &Vec2::new(x as usize, y as usize), &colors);
+                let brightness = diminished_light(light, depth);
+                blend_column(frame, blend, x as usize, y1.max(0) as usize..y2.max(0) as usize, |_| {
+                    let colors = material_color(textures, material, u, v);
                     v += vs;
-                }
+                    lit(colors, brightness)
+                });
             }
         }
 
     }
+
+    // Edge of a front face, pushed nearest first before the back faces are drawn
+    fn push_edge(&mut self, x: i32, y1: i32, y2: i32) {
+        match self.view {
+            SurfaceView::Bottom => self.near_edges[x as usize].push(y1),
+            SurfaceView::Top    => self.near_edges[x as usize].push(y2),
+            SurfaceView::Mid    => {  },
+        }
+    }
 }
 
 impl WallContext {
     
-    pub fn new() -> Self {
+    pub fn new(columns: Range<usize>) -> Self {
         WallContext {
             wall: [Vec3::zeros(); 4],
             depth: [0.0; 2],
             uclip: [0.0; 2],
+            urange: Vec2::new(0.0, 1.0),
             width: 0.0,
             face: Face::Back,
+            blend: Blend::Opaque,
+            light: FULL_LIGHT,
             distance: 0,
-            visiable: false
+            visiable: false,
+            columns: columns.start as i32..columns.end as i32,
         }
     }
 
     pub fn draw(
         &mut self, 
-        mut pixels: &mut Pixels, 
+        frame: &mut ColumnWriter,
+        depth: &mut DepthBuffer,
         surface: &mut Surface, 
-        player: &Player,
+        camera: &Camera,
         textures: &TextureSet,
         materials: &[&Material; 3]
     ) {
+        let config = &camera.config;
         // Draw only visible surface
         if !self.visiable { return; }
-        // y distance of bottom line
-        let dyb = self.distance_bottom_line();
-        // y distance of top line
-        let dyt = self.distance_top_line();
         // x distance
         let dx: i32 = self.large(); if dx == 0 { return; }
         // Hold initial x1 starting position
         let xs: i32 = self.wall[0].x;
-        // Let x1 and x2
-        let mut x1 = self.wall[0].x;
-        let mut x2 = self.wall[1].x;
-        // Clip X
-        x1 = clamp(x1, 0, consts::WIDTH as i32);
-        x2 = clamp(x2, 0, consts::WIDTH as i32);
         // Draw line
-        for x in x1..x2 {
+        for x in self.clipped_columns() {
             // compute u
             let u = match materials[0] {
                 Material::Texture(map) => self.u_texturing_prospective(textures, x - xs, dx, map),
                 _ => 0.0
             };
-            // From x1 to x, starting from closet point to current bottom
-            let mut y1: i32 = dyb * (((x - xs) as f32 + 0.5) as i32) / dx + self.wall[0].y;
-            // From x1 to x, starting from closet point to current top
-            let mut y2: i32 = dyt * (((x - xs) as f32 + 0.5) as i32) / dx + self.wall[2].y;
+            let (mut y1, mut y2) = self.edges_at(x, dx);
             // texture: i32 V
             let (v_coord, v_step)= match materials[0] {
                 Material::Texture(map) => self.v_texturing(textures, y1, y2, map),
                 _ => (0.0,0.0)
             }; 
             // Clip Y
-            y1 = clamp(y1, 0, consts::HEIGHT as i32);
-            y2 = clamp(y2, 0, consts::HEIGHT as i32);
+            y1 = clamp(y1, 0, config.height as i32);
+            y2 = clamp(y2, 0, config.height as i32);
+            // Walls hide the sprites behind their rows
+            let wall_depth = self.depth_at(x - xs, dx);
+            if self.face == Face::Front && y1 < y2 {
+                depth.paint(x as usize, PaintedWall { depth: wall_depth, bottom: y1, top: y2 });
+            }
             // Draw
             surface.draw(
-                &mut pixels,
-                &player,
+                frame,
+                camera,
                 &self.face, 
                 x, u, 
                 y1, y2, v_coord, v_step, 
-                textures, &materials
+                textures, materials, self.blend, (self.light, wall_depth)
             );
         }
     }
 
-    fn project(&mut self, player: &Player, face: &Face, wall2d: &[Vec2<i32>; 2], height: &SectorHeight) -> bool {
+    // Edges of the visible front face pushed to the surface, before anything is drawn
+    fn push_edges(&self, surface: &mut Surface, config: &RenderConfig) {
+        if !self.visiable || self.face != Face::Front { return; }
+        let dx: i32 = self.large(); if dx == 0 { return; }
+        for x in self.clipped_columns() {
+            let (y1, y2) = self.edges_at(x, dx);
+            surface.push_edge(x, clamp(y1, 0, config.height as i32), clamp(y2, 0, config.height as i32));
+        }
+    }
+
+    // Columns of the band the wall covers
+    fn clipped_columns(&self) -> Range<i32> {
+        clamp(self.wall[0].x, self.columns.start, self.columns.end)..clamp(self.wall[1].x, self.columns.start, self.columns.end)
+    }
+
+    // Bottom and top rows of the column x, not clipped
+    fn edges_at(&self, x: i32, dx: i32) -> (i32, i32) {
+        let xs = self.wall[0].x;
+        // From x1 to x, starting from closet point to current bottom
+        let y1: i32 = self.distance_bottom_line() * (((x - xs) as f32 + 0.5) as i32) / dx + self.wall[0].y;
+        // From x1 to x, starting from closet point to current top
+        let y2: i32 = self.distance_top_line() * (((x - xs) as f32 + 0.5) as i32) / dx + self.wall[2].y;
+        (y1, y2)
+    }
+
+    fn project(&mut self, player: &Player, config: &RenderConfig, face: &Face, seg: &Seg, wall: &Wall, sector: &Sector) -> bool {
         // Set values 
         self.face = face.clone();
+        self.blend = wall.blend;
+        self.light = sector.light;
+        self.urange = seg.u;
+        let height = &sector.height;
+        let position = player.position.xy().as_vec::<f32>();
+        let wall2d = [seg.point1, seg.point2];
         // Wall direction
         let points = {
             match face {
                 Face::Front => [
-                    wall2d[0] - player.position.xy(),
-                    wall2d[1] - player.position.xy(),
+                    wall2d[0] - position,
+                    wall2d[1] - position,
                 ],
                 Face::Back => [
-                    wall2d[1] - player.position.xy(),
-                    wall2d[0] - player.position.xy(),
+                    wall2d[1] - position,
+                    wall2d[0] - position,
                 ],
             }
         };
         // Save wall width
-        self.width = wall2d[0].as_vec::<f32>().distance(&wall2d[1].as_vec::<f32>());
+        self.width = wall2d[0].distance(&wall2d[1]);
         // Cache cos and sin
         let pcos = player.cos();
         let psin = player.sin();
         // First line in 3D
         for i in 0..2 {
             // World X
-            self.wall[i].x = ((points[i].x as f32) * pcos - (points[i].y as f32) * psin) as i32;
+            self.wall[i].x = (points[i].x * pcos - points[i].y * psin) as i32;
             // World Y
-            self.wall[i].y = ((points[i].y as f32) * pcos + (points[i].x as f32) * psin) as i32;
+            self.wall[i].y = (points[i].y * pcos + points[i].x * psin) as i32;
             // World Z
             self.wall[i].z = ((height.bottom - player.position.z) as f32
-                           + ((player.updown * self.wall[i].y) as f32 / consts::UPDOWN_FACTOR))
+                           + ((player.updown * self.wall[i].y as f32) / consts::UPDOWN_FACTOR))
                            as i32;
 
             // Second line,  X,Y are the same
@@ -343,7 +611,7 @@ impl WallContext {
             self.wall[i + 2].y = self.wall[i].y;
             // Z is to be recompute with new height
             self.wall[i + 2].z = ((height.top - player.position.z) as f32
-                               + ((player.updown * self.wall[i].y) as f32 / consts::UPDOWN_FACTOR))
+                               + ((player.updown * self.wall[i].y as f32) / consts::UPDOWN_FACTOR))
                                as i32;
         }
         // Distance
@@ -380,8 +648,8 @@ impl WallContext {
         }
         // Screen position
         for i in 0..4 {
-            self.wall[i].x = (self.wall[i].x * render::width_on_fov()) / self.wall[i].y + consts::H_WIDTH as i32;
-            self.wall[i].y = (self.wall[i].z * render::width_on_fov()) / self.wall[i].y + consts::H_HEIGHT as i32;
+            self.wall[i].x = (self.wall[i].x * config.width_on_fov()) / self.wall[i].y + config.half_width();
+            self.wall[i].y = (self.wall[i].z * config.width_on_fov()) / self.wall[i].y + config.half_height();
         }
         // Draw
         self.visiable = true;
@@ -417,11 +685,17 @@ impl WallContext {
         self.wall[1].x - self.wall[0].x
     }
 
+    // Depth of a column, 1/z is linear on screen
+    fn depth_at(&self, wx: i32, dx: i32) -> f32 {
+        let a = wx as f32 / (dx as f32);
+        1.0 / lerp(1.0 / self.depth[0], 1.0 / self.depth[1], a)
+    }
+
     fn u_texturing_linear(&self, textures: &TextureSet, wx: i32, dx: i32, map: &TextureMapping) -> f32 {
         let a = wx as f32 / (dx as f32);
         let u0 = self.uclip[0];
         let u1 = 1.0 - self.uclip[1];
-        let u = lerp(u0,u1,a);
+        let u = lerp(self.urange.x, self.urange.y, lerp(u0,u1,a));
         return u * (map.uv.x as f32) * (textures.set[map.texture].dimensions.x as f32);
     }
 
@@ -435,7 +709,7 @@ impl WallContext {
         let iz1 = 1.0 / z1;
         let utop = lerp(u0 * iz0,u1 * iz1,a);
         let ubottom = lerp(iz0, iz1, a);
-        let u = utop / ubottom;
+        let u = lerp(self.urange.x, self.urange.y, utop / ubottom);
         return u * (map.uv.x as f32) * (textures.set[map.texture].dimensions.x as f32);
     }
 
@@ -449,52 +723,570 @@ impl WallContext {
 
 impl SectorContext {
     
-    pub fn new(index: usize) -> Self {
+    pub fn new(index: usize, config: &RenderConfig) -> Self {
         SectorContext {
             index: index,
             surface: Surface {
-                points: vec![0; consts::WIDTH as usize],
+                points: vec![0; config.width as usize],
                 view: SurfaceView::Mid,
-                wall_offset: 0
+                wall_offset: 0,
+                stacked: false,
+                near_edges: Vec::new(),
             } ,
             distance: 0,
         }
     }
 
-    pub fn start<'a>(&mut self, position: &Vec3<i32>, sector: &Sector) -> std::slice::Iter<'a, Face> {
+    pub fn start<'a>(&mut self, position: &Vec3<i32>, sector: &Sector, config: &RenderConfig, stacked: bool) -> std::slice::Iter<'a, Face> {
         // Clear distance
         self.distance = 0;
+        self.surface.stacked = stacked;
+        if stacked {
+            self.surface.near_edges.resize(config.width as usize, Vec::new());
+            self.surface.near_edges.iter_mut().for_each(Vec::clear);
+        }
         // Draw top/mid/bottom
         if position.z > sector.height.top {
             self.surface.view = SurfaceView::Top;
             self.surface.wall_offset = sector.height.top;
-            self.surface.points.fill(consts::HEIGHT as i32);
-            [Face::Front, Face::Back].iter()
+            self.surface.points.fill(config.height as i32);
         } else if position.z < sector.height.bottom {
             self.surface.view = SurfaceView::Bottom;
             self.surface.wall_offset = sector.height.bottom;
             self.surface.points.fill(0);
-            [Face::Front, Face::Back].iter()
         } else {
             self.surface.view = SurfaceView::Mid;
-            [Face::Front].iter()
+        }
+        self.faces()
+    }
+
+    // Planes need the back faces, seen only from above or below the sector
+    fn faces<'a>(&self) -> std::slice::Iter<'a, Face> {
+        match self.surface.view {
+            SurfaceView::Mid => [Face::Front].iter(),
+            _ => [Face::Front, Face::Back].iter(),
         }
     }
 
 }
 
-impl Render {
-    pub fn new(world: Rc<World>, textures: Rc<TextureSet>) -> Self {
-        Render {
-            world: Rc::clone(&world),
-            textures: Rc::clone(&textures),
-            sectors_context: (0..world.sectors.len()).map(|i| SectorContext::new(i)).collect(),
+impl Camera {
+
+    pub fn new(player: &Player, config: &RenderConfig) -> Self {
+        Camera {
+            player: *player,
+            config: *config,
+            cos: player.cos(),
+            sin: player.sin(),
+        }
+    }
+
+    // Point in front of the player, y is the depth
+    fn view(&self, point: &Vec2<f32>) -> Vec2<f32> {
+        let p = *point - self.player.position.xy().as_vec::<f32>();
+        Vec2::new(p.x * self.cos - p.y * self.sin, p.y * self.cos + p.x * self.sin)
+    }
+
+    fn screen_x(&self, view: &Vec2<f32>) -> f32 {
+        view.x * self.config.width_on_fov() as f32 / view.y + self.config.half_width() as f32
+    }
+
+    fn screen_y(&self, height: i32, depth: f32) -> f32 {
+        let z = (height - self.player.position.z) as f32 + (self.player.updown * depth) / consts::UPDOWN_FACTOR;
+        z * self.config.width_on_fov() as f32 / depth + self.config.half_height() as f32
+    }
+
+    // First screen row at or above y
+    fn row(&self, y: f32) -> i32 {
+        clamp(y.ceil(), -1.0, self.config.height as f32 + 1.0) as i32
+    }
+
+    // Columns covered by a wall in view space, clipped to the near plane
+    fn columns(&self, a: &Vec2<f32>, b: &Vec2<f32>) -> Option<(usize, usize)> {
+        if a.y < NEAR_PLANE && b.y < NEAR_PLANE {
+            return None;
+        }
+        let clip = |p: &Vec2<f32>, q: &Vec2<f32>| {
+            if p.y < NEAR_PLANE {
+                let s = (NEAR_PLANE - p.y) / (q.y - p.y);
+                Vec2::new(p.x + (q.x - p.x) * s, NEAR_PLANE)
+            } else {
+                *p
+            }
+        };
+        let x1 = self.screen_x(&clip(a, b));
+        let x2 = self.screen_x(&clip(b, a));
+        let left = x1.min(x2).floor().max(0.0) as usize;
+        let right = (x1.max(x2).ceil().max(0.0) as usize).min(self.config.width as usize);
+        if left < right { Some((left, right)) } else { None }
+    }
+
+    // Ray of the column x against the wall a-b, returns (t, depth)
+    fn intersect(&self, x: usize, a: &Vec2<f32>, b: &Vec2<f32>) -> Option<(f32, f32)> {
+        let k = (x as f32 + 0.5 - self.config.half_width() as f32) / self.config.width_on_fov() as f32;
+        let d = (b.x - a.x) - k * (b.y - a.y);
+        if d.abs() < 1e-6 {
+            return None;
         }
+        let t = (k * a.y - a.x) / d;
+        if !(0.0..=1.0).contains(&t) {
+            return None;
+        }
+        let depth = a.y + t * (b.y - a.y);
+        if depth < NEAR_PLANE { None } else { Some((t, depth)) }
     }
+}
+
+// Both sides of a portal lie on the same line
+fn same_line(left: &Wall, right: &Wall) -> bool {
+    (left.point1 == right.point2 && left.point2 == right.point1)
+    || (left.point1 == right.point1 && left.point2 == right.point2)
+}
 
-    pub fn draw(&mut self, mut pixels: &mut Pixels, player: &Player) {
+fn draw_plane(
+    frame: &mut ColumnWriter,
+    camera: &Camera,
+    textures: &TextureSet,
+    (material, height): (&Material, i32),
+    light: u8,
+    x: usize,
+    rows: Range<i32>
+) {
+    if rows.is_empty() { return; }
+    let tile = plane_tile(textures, &camera.config, material);
+    let (look_updown, move_updown) = look_and_move_updown(&camera.player, &camera.config, height);
+    frame.column(x, rows.start.max(0) as usize..rows.end.max(0) as usize, |y| {
+        let (pu, pv) = plane_uv(&camera.player, &camera.config, x as i32, y as i32, look_updown, move_updown, tile);
+        let brightness = diminished_light(light, plane_depth(&camera.config, y as i32, look_updown, move_updown));
+        lit(material_color(textures, material, pu, pv), brightness)
+    });
+}
+
+// Rows of a wall whose edges are (y_low, y_high) on screen, steps keep the texture of the whole wall
+fn draw_wall(
+    frame: &mut ColumnWriter,
+    textures: &TextureSet,
+    (material, blend): (&Material, Blend),
+    light: u8,
+    hit: &ColumnHit,
+    rows: Range<i32>,
+    (y_low, y_high): (f32, f32)
+) {
+    if rows.is_empty() { return; }
+    let (u, v_scale) = match material {
+        Material::Texture(map) => {
+            let dimensions = &textures.set[map.texture].dimensions;
+            (hit.t * (map.uv.x * dimensions.x as i32) as f32,
+             (map.uv.y * dimensions.y as i32) as f32 / (y_high - y_low).max(1.0))
+        },
+        _ => (0.0, 0.0)
+    };
+    let brightness = diminished_light(light, hit.depth);
+    blend_column(frame, blend, hit.x, rows.start.max(0) as usize..rows.end.max(0) as usize, |y| {
+        let v = ((y as f32 - y_low) * v_scale).max(0.0);
+        lit(material_color(textures, material, u, v), brightness)
+    });
+}
+
+impl PortalContext {
+
+    pub fn new(config: &RenderConfig) -> Self {
+        let width = config.width as usize;
+        PortalContext {
+            bottom: vec![0; width],
+            top: vec![config.height as i32; width],
+            hits: vec![None; width],
+            sectors: vec![0; width],
+            crossed: vec![None; width],
+            depth: DepthBuffer::new(config),
+            masked: Vec::new(),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        frame: &mut ColumnWriter,
+        scene: &Scene,
+        camera: &Camera,
+        sector: usize,
+        columns: Range<usize>
+    ) {
+        let (world, textures) = (scene.world, scene.textures);
+        // Whole screen open
+        self.bottom.fill(0);
+        self.top.fill(camera.config.height as i32);
+        // From the player sector, front to back
+        let mut windows = VecDeque::from([PortalWindow {
+            sector,
+            x1: columns.start,
+            x2: columns.end,
+            entry: vec![NEAR_PLANE; columns.len()],
+            wall: None,
+        }]);
+        let mut visits = 0;
+        while let Some(window) = windows.pop_front() {
+            visits += 1;
+            if MAX_PORTAL_WINDOWS < visits { break; }
+            self.find_walls(world, camera, &window);
+            // Draw columns, columns seen through the same portal become a new window
+            let mut next: Option<PortalWindow> = None;
+            for x in window.x1..window.x2 {
+                let through = match self.hits[x] {
+                    Some(hit) => self.draw_column(frame, world, textures, camera, window.sector, hit),
+                    None => None
+                };
+                match (through, next.as_mut()) {
+                    (Some((neighbor, wall, depth)), Some(open))
+                        if open.sector == neighbor && open.wall == Some(wall) && open.x2 == x => {
+                        open.x2 += 1;
+                        open.entry.push(depth);
+                    },
+                    (through, _) => {
+                        windows.extend(next.take());
+                        next = through.map(|(neighbor, wall, depth)| PortalWindow {
+                            sector: neighbor,
+                            x1: x,
+                            x2: x + 1,
+                            entry: vec![depth],
+                            wall: Some(wall),
+                        });
+                    }
+                }
+            }
+            windows.extend(next);
+        }
+    }
+
+    pub fn draw_bsp(
+        &mut self,
+        frame: &mut ColumnWriter,
+        scene: &Scene,
+        bsp: &Bsp,
+        camera: &Camera,
+        sector: usize,
+        columns: Range<usize>
+    ) {
+        let (world, textures) = (scene.world, scene.textures);
+        // Whole screen open, every column starts in the player sector
+        self.bottom.fill(0);
+        self.top.fill(camera.config.height as i32);
+        self.sectors.fill(sector);
+        self.crossed.fill(None);
+        let mut open_columns = columns.len();
+        bsp.walk(&camera.player.position.xy().as_vec::<f32>(), |segs| {
+            // Nearest seg of the leaf in each column, of the sector the column is in
+            self.hits[columns.clone()].fill(None);
+            for seg in segs {
+                let a = camera.view(&seg.point1);
+                let b = camera.view(&seg.point2);
+                let (x1, x2) = match camera.columns(&a, &b) {
+                    Some(columns) => columns,
+                    None => continue,
+                };
+                for x in x1.max(columns.start)..x2.min(columns.end) {
+                    if self.top[x] <= self.bottom[x] || self.sectors[x] != seg.sector {
+                        continue;
+                    }
+                    if self.crossed[x].is_some_and(|wall| same_line(&world.walls[seg.wall], &world.walls[wall])) {
+                        continue;
+                    }
+                    if let Some((t, depth)) = camera.intersect(x, &a, &b) {
+                        if self.hits[x].is_none_or(|hit| depth < hit.depth) {
+                            let t = lerp(seg.u.x, seg.u.y, t);
+                            self.hits[x] = Some(ColumnHit { x, wall: seg.wall, t, depth });
+                        }
+                    }
+                }
+            }
+            for x in columns.clone() {
+                let hit = match self.hits[x] {
+                    Some(hit) => hit,
+                    None => continue,
+                };
+                match self.draw_column(frame, world, textures, camera, self.sectors[x], hit) {
+                    Some((neighbor, wall, _)) => {
+                        self.sectors[x] = neighbor;
+                        self.crossed[x] = Some(wall);
+                    },
+                    None => open_columns -= 1,
+                }
+            }
+            0 < open_columns
+        });
+    }
+
+    // Nearest wall of the window sector behind the portal, for each column
+    fn find_walls(&mut self, world: &World, camera: &Camera, window: &PortalWindow) {
+        self.hits[window.x1..window.x2].fill(None);
+        let sector = &world.sectors[window.sector];
+        let walls = match world.sector_walls(sector) {
+            Some(walls) => walls,
+            None => return,
+        };
+        let entered = window.wall.map(|wall| &world.walls[wall]);
+        for (offset, wall) in walls.iter().enumerate() {
+            // Other side of the portal just crossed
+            if entered.is_some_and(|entered| same_line(wall, entered)) {
+                continue;
+            }
+            let a = camera.view(&wall.point1.as_vec::<f32>());
+            let b = camera.view(&wall.point2.as_vec::<f32>());
+            let (x1, x2) = match camera.columns(&a, &b) {
+                Some(columns) => columns,
+                None => continue,
+            };
+            for x in x1.max(window.x1)..x2.min(window.x2) {
+                if let Some((t, depth)) = camera.intersect(x, &a, &b) {
+                    let nearer = self.hits[x].is_none_or(|hit| depth < hit.depth);
+                    if window.entry[x - window.x1] <= depth && nearer {
+                        self.hits[x] = Some(ColumnHit { x, wall: sector.wall.x as usize + offset, t, depth });
+                    }
+                }
+            }
+        }
+    }
+
+    // Draws ceiling, floor and wall of a column, returns the portal to go through if still open
+    fn draw_column(
+        &mut self,
+        frame: &mut ColumnWriter,
+        world: &World,
+        textures: &TextureSet,
+        camera: &Camera,
+        sector_id: usize,
+        hit: ColumnHit
+    ) -> Option<(usize, usize, f32)> {
+        let x = hit.x;
+        let sector = &world.sectors[sector_id];
+        let wall = &world.walls[hit.wall];
+        let (bottom, top) = (self.bottom[x], self.top[x]);
+        if top <= bottom { return None; }
+        // Wall edges
+        let y_bottom = camera.screen_y(sector.height.bottom, hit.depth);
+        let y_top = camera.screen_y(sector.height.top, hit.depth);
+        let wall_bottom = clamp(camera.row(y_bottom), bottom, top);
+        let wall_top = clamp(camera.row(y_top), bottom, top);
+        // Ceiling and floor
+        draw_plane(frame, camera, textures, (&sector.material[0], sector.height.top), sector.light, x, wall_top..top);
+        draw_plane(frame, camera, textures, (&sector.material[1], sector.height.bottom), sector.light, x, bottom..wall_bottom);
+        // Solid wall, column closed
+        let neighbor = match wall.portal {
+            Some(neighbor) if neighbor < world.sectors.len() => neighbor,
+            _ => {
+                draw_wall(frame, textures, (&wall.material, wall.blend), sector.light, &hit, wall_bottom..wall_top, (y_bottom, y_top));
+                self.top[x] = self.bottom[x];
+                self.depth.wall(x, hit.depth);
+                return None;
+            }
+        };
+        // Steps toward the neighbor sector
+        let height = &world.sectors[neighbor].height;
+        let y_upper = camera.screen_y(height.top.min(sector.height.top), hit.depth);
+        let y_lower = camera.screen_y(height.bottom.max(sector.height.bottom), hit.depth);
+        let upper = clamp(camera.row(y_upper), wall_bottom, wall_top);
+        let lower = clamp(camera.row(y_lower), wall_bottom, wall_top);
+        draw_wall(frame, textures, (&wall.material, Blend::Opaque), sector.light, &hit, upper..wall_top, (y_bottom, y_top));
+        draw_wall(frame, textures, (wall.lower_step(), Blend::Opaque), sector.light, &hit, wall_bottom..lower, (y_bottom, y_top));
+        // Window left open
+        self.bottom[x] = lower;
+        self.top[x] = upper.max(lower);
+        self.depth.portal(x, PortalClip { depth: hit.depth, bottom: self.bottom[x], top: self.top[x] });
+        // Opening drawn later, over what is seen through it
+        if !wall.blend.is_opaque() && lower < upper {
+            self.masked.push(MaskedColumn { hit, rows: (lower, upper), edges: (y_bottom, y_top), light: sector.light });
+        }
+        if self.bottom[x] < self.top[x] { Some((neighbor, hit.wall, hit.depth)) } else { None }
+    }
+}
+
+impl DepthBuffer {
+
+    pub fn new(config: &RenderConfig) -> Self {
+        let width = config.width as usize;
+        DepthBuffer {
+            walls: vec![f32::INFINITY; width],
+            portals: vec![Vec::new(); width],
+            painted: vec![Vec::new(); width],
+        }
+    }
+
+    pub fn clear(&mut self, columns: Range<usize>) {
+        self.walls[columns.clone()].fill(f32::INFINITY);
+        for portals in &mut self.portals[columns.clone()] {
+            portals.clear();
+        }
+        for painted in &mut self.painted[columns] {
+            painted.clear();
+        }
+    }
+
+    // Solid wall closing the column
+    pub fn wall(&mut self, x: usize, depth: f32) {
+        if let Some(wall) = self.walls.get_mut(x) {
+            *wall = depth;
+        }
+    }
+
+    pub fn paint(&mut self, x: usize, wall: PaintedWall) {
+        if let Some(painted) = self.painted.get_mut(x) {
+            painted.push(wall);
+        }
+    }
+
+    // The row y is left on screen by a painted wall nearer than the depth
+    fn hides(&self, x: usize, y: i32, depth: f32) -> bool {
+        self.painted[x]
+            .iter()
+            .rev()
+            .find(|wall| wall.bottom <= y && y < wall.top)
+            .is_some_and(|wall| wall.depth <= depth)
+    }
+
+    pub fn portal(&mut self, x: usize, clip: PortalClip) {
+        self.portals[x].push(clip);
+    }
+
+    // Rows of the column seen at a depth
+    fn rows(&self, x: usize, depth: f32, rows: Range<i32>) -> Range<i32> {
+        if self.walls[x] <= depth {
+            return 0..0;
+        }
+        self.portals[x]
+            .iter()
+            .filter(|clip| clip.depth < depth)
+            .fold(rows, |rows, clip| rows.start.max(clip.bottom)..rows.end.min(clip.top))
+    }
+}
+
+impl Sprite {
+
+    // None behind the player or without a texture, lit by the light of the sector it stands in
+    fn project(camera: &Camera, textures: &TextureSet, index: usize, thing: &Thing, light: u8) -> Option<Self> {
+        let texture = textures.set.get(thing.texture)?;
+        let view = camera.view(&thing.position.xy().as_vec::<f32>());
+        if view.y < NEAR_PLANE || texture.dimensions.y == 0 {
+            return None;
+        }
+        // Width from the texture aspect, centered on the position
+        let width = thing.height as f32 * texture.dimensions.x as f32 / texture.dimensions.y as f32;
+        let half = width / 2.0 * camera.config.width_on_fov() as f32 / view.y;
+        let center = camera.screen_x(&view);
+        Some(Sprite {
+            thing: index,
+            depth: view.y,
+            left: center - half,
+            right: center + half,
+            y_low: camera.screen_y(thing.position.z, view.y),
+            y_high: camera.screen_y(thing.position.z + thing.height, view.y),
+            brightness: diminished_light(light, view.y),
+        })
+    }
+
+    fn draw(&self, frame: &mut ColumnWriter, camera: &Camera, textures: &TextureSet, depth: &DepthBuffer, thing: &Thing, columns: Range<usize>) {
+        let texture = &textures.set[thing.texture];
+        let dimensions = texture.dimensions.as_vec::<f32>();
+        let x1 = (self.left.floor().max(0.0) as usize).max(columns.start);
+        let x2 = (self.right.ceil().max(0.0) as usize).min(columns.end);
+        let u_scale = dimensions.x / (self.right - self.left).max(1.0);
+        let v_scale = dimensions.y / (self.y_high - self.y_low).max(1.0);
+        let height = camera.config.height as i32;
+        for x in x1..x2 {
+            let u = (x as f32 + 0.5 - self.left) * u_scale;
+            if !(0.0..dimensions.x).contains(&u) {
+                continue;
+            }
+            let rows = depth.rows(x, self.depth, camera.row(self.y_low)..camera.row(self.y_high));
+            let rows = rows.start.max(0)..rows.end.min(height);
+            if rows.is_empty() {
+                continue;
+            }
+            frame.masked_column(x, rows.start as usize..rows.end as usize, |y| {
+                if depth.hides(x, y as i32, self.depth) {
+                    return [0; 4];
+                }
+                let v = ((y as f32 - self.y_low) * v_scale).clamp(0.0, dimensions.y - 1.0);
+                lit(texture.uv_pixel_shade(u, v, 0), self.brightness)
+            });
+        }
+    }
+}
+
+impl ColumnsContext {
+
+    pub fn new(world: &World, config: &RenderConfig, columns: Range<usize>) -> Self {
+        ColumnsContext {
+            columns,
+            sectors_context: (0..world.sectors.len()).map(|i| SectorContext::new(i, config)).collect(),
+            portal_context: PortalContext::new(config),
+            sprites: Vec::new(),
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut ColumnWriter, scene: &Scene) {
+        let columns = self.columns.clone();
+        let camera = Camera::new(scene.player, scene.config);
+        self.portal_context.depth.clear(columns.clone());
+        self.portal_context.masked.clear();
+        // Portals need the player inside of a sector, out of every sector BSP paints its segs
+        match (scene.sector, scene.bsp, scene.mode) {
+            (None, Some(bsp), RenderMode::Bsp) => {
+                self.draw_bsp_sectors(frame, scene, bsp, &camera, columns.clone())
+            },
+            (None, _, _) | (_, _, RenderMode::Sectors) => {
+                self.draw_sectors(frame, scene, &camera, columns.clone())
+            },
+            (Some(sector), Some(bsp), RenderMode::Bsp) => {
+                self.portal_context.draw_bsp(frame, scene, bsp, &camera, sector, columns.clone())
+            },
+            (Some(sector), _, _) => {
+                self.portal_context.draw(frame, scene, &camera, sector, columns.clone())
+            },
+        }
+        self.draw_things(frame, scene, &camera, columns);
+    }
+
+    // Sprites and openings of portals that are not opaque, back to front, over the walls
+    fn draw_things(&mut self, frame: &mut ColumnWriter, scene: &Scene, camera: &Camera, columns: Range<usize>) {
+        self.sprites.clear();
+        self.sprites.extend(
+            scene.world.things
+                .iter()
+                .enumerate()
+                .filter_map(|(index, thing)| {
+                    let light = scene.world
+                        .sector_at(&thing.position.xy().as_vec::<f32>())
+                        .map_or(FULL_LIGHT, |sector| scene.world.sectors[sector].light);
+                    Sprite::project(camera, scene.textures, index, thing, light)
+                })
+        );
+        self.sprites.sort_by(|left, right| right.depth.total_cmp(&left.depth));
+        let masked = &mut self.portal_context.masked;
+        masked.sort_by(|left, right| right.hit.depth.total_cmp(&left.hit.depth));
+        let (mut sprites, mut masked) = (self.sprites.iter().peekable(), masked.iter().peekable());
+        loop {
+            // The farther of the next sprite and the next opening
+            let sprite_first = match (sprites.peek(), masked.peek()) {
+                (Some(sprite), Some(column)) => column.hit.depth <= sprite.depth,
+                (sprite, _) => sprite.is_some(),
+            };
+            if let Some(sprite) = sprites.next_if(|_| sprite_first) {
+                let thing = &scene.world.things[sprite.thing];
+                sprite.draw(frame, camera, scene.textures, &self.portal_context.depth, thing, columns.clone());
+            } else if let Some(column) = masked.next() {
+                let wall = &scene.world.walls[column.hit.wall];
+                let (bottom, top) = column.rows;
+                draw_wall(frame, scene.textures, (wall.opening(), wall.blend), column.light, &column.hit, bottom..top, column.edges);
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Painter's algorithm, used when the player is outside of every sector
+    fn draw_sectors(&mut self, frame: &mut ColumnWriter, scene: &Scene, camera: &Camera, columns: Range<usize>) {
+        let player = scene.player;
         // Init
-        let mut wall_context = WallContext::new();
+        let mut wall_context = WallContext::new(columns);
         // Sort
         self.sectors_context.sort_by(|left, right| right.distance.cmp(&left.distance));    
         // Mut ref to self.sectors_context
@@ -502,17 +1294,16 @@ impl Render {
         // For each sector
         for context in sectors_context {
             // Ref to sector
-            let sector = &self.world.sectors[context.index];
+            let sector = &scene.world.sectors[context.index];
             // Let wall count
             let mut count_walls : i32 = 0;
             // Back and front
-            for face in context.start(&player.position, &sector) {
+            for face in context.start(&player.position, sector, scene.config, false) {
                 // For each wall
                 for wall_id in sector.wall.x..sector.wall.y {
                     // Wall
-                    let wall = &self.world.walls[wall_id as usize];
-                    // Wall 2D
-                    let wall2d = [wall.point1, wall.point2];
+                    let wall = &scene.world.walls[wall_id as usize];
+                    let seg = Seg::whole(wall, wall_id as usize, context.index);
                     // Material set
                     let materials = [
                         &wall.material,
@@ -520,13 +1311,14 @@ impl Render {
                         &sector.material[1],
                     ];
                     // From a wall described as two points + height, to 3D world
-                    if wall_context.project(&player, &face, &wall2d, &sector.height) {
+                    if wall_context.project(player, scene.config, face, &seg, wall, sector) {
                         // Draw
                         wall_context.draw(
-                            &mut pixels,
+                            frame,
+                            &mut self.portal_context.depth,
                             &mut context.surface, 
-                            &player,
-                            self.textures.as_ref(),
+                            camera,
+                            scene.textures,
                             &materials
                         );
                     }
@@ -541,4 +1333,132 @@ impl Render {
             }
         }
     }
+
+    // Painter's algorithm on the segs of the BSP, back to front whatever the shape of the sectors
+    fn draw_bsp_sectors(&mut self, frame: &mut ColumnWriter, scene: &Scene, bsp: &Bsp, camera: &Camera, columns: Range<usize>) {
+        let player = scene.player;
+        let point = player.position.xy().as_vec::<f32>();
+        // Front to back, in a leaf the segs seen from behind are the nearest
+        let mut segs: Vec<&Seg> = Vec::new();
+        bsp.walk(&point, |leaf| {
+            segs.extend(leaf.iter().filter(|seg| seg.behind(&point)));
+            segs.extend(leaf.iter().filter(|seg| !seg.behind(&point)));
+            true
+        });
+        // Init
+        let mut wall_context = WallContext::new(columns);
+        self.sectors_context.sort_by_key(|context| context.index);
+        for context in self.sectors_context.iter_mut() {
+            let sector = &scene.world.sectors[context.index];
+            let _ = context.start(&player.position, sector, scene.config, true);
+        }
+        // Edges of the front faces, the planes of the back faces start from them
+        for seg in segs.iter() {
+            let sector = &scene.world.sectors[seg.sector];
+            let context = &mut self.sectors_context[seg.sector];
+            if wall_context.project(player, scene.config, &Face::Front, seg, &scene.world.walls[seg.wall], sector) {
+                wall_context.push_edges(&mut context.surface, scene.config);
+            }
+        }
+        // Back to front
+        for seg in segs.iter().rev() {
+            let sector = &scene.world.sectors[seg.sector];
+            let wall = &scene.world.walls[seg.wall];
+            let context = &mut self.sectors_context[seg.sector];
+            let materials = [
+                &wall.material,
+                &sector.material[0],
+                &sector.material[1],
+            ];
+            for face in context.faces() {
+                if wall_context.project(player, scene.config, face, seg, wall, sector) {
+                    wall_context.draw(
+                        frame,
+                        &mut self.portal_context.depth,
+                        &mut context.surface,
+                        camera,
+                        scene.textures,
+                        &materials
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Bands of columns, as many as the threads but never empty
+fn bands(world: &World, config: &RenderConfig) -> Vec<ColumnsContext> {
+    let width = config.width as usize;
+    let count = config.threads.clamp(1, width.max(1));
+    (0..count)
+        .map(|i| ColumnsContext::new(world, config, i * width / count..(i + 1) * width / count))
+        .collect()
+}
+
+impl Render {
+    pub fn new(world: Rc<World>, textures: Rc<TextureSet>, config: RenderConfig) -> Self {
+        Render {
+            world: Rc::clone(&world),
+            textures: Rc::clone(&textures),
+            config,
+            mode: RenderMode::Portals,
+            bsp: None,
+            bands: bands(&world, &config),
+        }
+    }
+
+    // New frame size, the scale is kept
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.config.width = width;
+        self.config.height = height;
+        self.bands = bands(&self.world, &self.config);
+    }
+
+    // BSP of the same world, drawn by RenderMode::Bsp
+    pub fn with_bsp(mut self, bsp: Rc<Bsp>) -> Self {
+        self.bsp = Some(bsp);
+        self.mode = RenderMode::Bsp;
+        self
+    }
+
+    pub fn draw(&mut self, frame: &mut dyn FrameBuffer, player: &Player) {
+        let scene = Scene {
+            world: &self.world,
+            textures: &self.textures,
+            bsp: self.bsp.as_deref(),
+            config: &self.config,
+            mode: self.mode,
+            player,
+            sector: self.player_sector(player),
+        };
+        // One band, or a frame of another size, is drawn by this thread
+        let size = Vec2::new(self.config.width as usize, self.config.height as usize);
+        if self.bands.len() == 1 || frame.dimensions() != size {
+            let mut writer = ColumnWriter::new(frame);
+            for band in self.bands.iter_mut() {
+                band.draw(&mut writer, &scene);
+            }
+            return;
+        }
+        // Each band draws its own columns of the frame in a thread
+        let columns: Vec<Range<usize>> = self.bands.iter().map(|band| band.columns.clone()).collect();
+        let writers = ColumnWriter::bands(frame, &columns);
+        thread::scope(|scope| {
+            for (band, mut writer) in self.bands.iter_mut().zip(writers) {
+                let scene = &scene;
+                scope.spawn(move || band.draw(&mut writer, scene));
+            }
+        });
+    }
+
+    // Sector the player stands in, between its floor and ceiling
+    fn player_sector(&self, player: &Player) -> Option<usize> {
+        let sector = self.world.sector_at(&player.position.xy().as_vec::<f32>())?;
+        let height = &self.world.sectors[sector].height;
+        if height.bottom <= player.position.z && player.position.z <= height.top {
+            Some(sector)
+        } else {
+            None
+        }
+    }
 }
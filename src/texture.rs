@@ -1,14 +1,24 @@
 #![allow(dead_code)]
-use crate::windows::draw_pixel;
-use pixels::Pixels;
+use crate::framebuffer::FrameBuffer;
 
 // Using, d3d
 use crate::math::{Vec2, no_negative};
 use crate::tga::{decode_tga, from_rgba5551_to_rgba_32};
+use crate::wad::{read_i16, read_i32, read_name, Wad, WadError};
 // Using
+use std::collections::HashMap;
 use std::fs::{self, DirEntry, ReadDir};
 use std::ffi::OsStr;
 
+// Doom flats are 64x64 raw palette indices
+const FLAT_SIZE: usize = 64;
+const PALETTE_SIZE: usize = 256 * 3;
+// Column of a picture without more posts
+const END_OF_COLUMN: u8 = 0xff;
+
+// Width, height and palette indices of a picture, None is transparent
+type Picture = (usize, usize, Vec<Option<u8>>);
+
 pub struct Texture {
     pub dimensions: Vec2<usize>,
     pub channels: u8,
@@ -68,10 +78,39 @@ impl Texture {
         }
     }
 
-    pub fn draw(&self, mut pixels: &mut Pixels) {
+    // Textures are stored bottom row first, as an uncompressed TGA
+    fn from_indexed(width: usize, height: usize, indices: &[Option<u8>], palette: &[u8]) -> Self {
+        let mut data = vec![0u8; width * height * 4];
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(index) = indices[y * width + x] {
+                    let color = &palette[index as usize * 3..index as usize * 3 + 3];
+                    let offset = ((height - y - 1) * width + x) * 4;
+                    data[offset..offset + 4].copy_from_slice(&[color[0], color[1], color[2], 0xff]);
+                }
+            }
+        }
+        Texture {
+            dimensions: Vec2::new(width, height),
+            channels: 4,
+            data,
+        }
+    }
+
+    pub fn from_flat(lump: &[u8], palette: &[u8]) -> Self {
+        let indices: Vec<Option<u8>> = lump.iter().map(|index| Some(*index)).collect();
+        Texture::from_indexed(FLAT_SIZE, FLAT_SIZE, &indices, palette)
+    }
+
+    pub fn from_picture(lump: &[u8], palette: &[u8]) -> Option<Self> {
+        let (width, height, indices) = decode_picture(lump)?;
+        Some(Texture::from_indexed(width, height, &indices, palette))
+    }
+
+    pub fn draw(&self, frame: &mut dyn FrameBuffer) {
         for y in 0..self.dimensions.y {
             for x in 0..self.dimensions.x {
-                draw_pixel(&mut pixels, &Vec2::new(x, y), self.pixel(x, y));
+                frame.draw_pixel(&Vec2::new(x, y), self.pixel(x, y));
             }
         }
     }
@@ -79,6 +118,7 @@ impl Texture {
 
 pub struct TextureSet {
     pub set: Vec<Texture>,
+    pub names: HashMap<String, usize>,
 }
 
 fn sort_paths(entries: ReadDir) -> Vec<DirEntry> {
@@ -95,9 +135,72 @@ fn sort_paths(entries: ReadDir) -> Vec<DirEntry> {
     return paths;
 }
 
+impl Default for TextureSet {
+    fn default() -> Self {
+        TextureSet::new()
+    }
+}
+
 impl TextureSet {
-    fn new() -> Self {
-        TextureSet { set: vec![] }
+    pub fn new() -> Self {
+        TextureSet { set: vec![], names: HashMap::new() }
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.get(&name.to_ascii_uppercase()).copied()
+    }
+
+    fn push(&mut self, name: &str, texture: Texture) {
+        self.names.insert(name.to_ascii_uppercase(), self.set.len());
+        self.set.push(texture);
+    }
+
+    // Flats, patches, sprites and the composite textures of TEXTURE1/TEXTURE2, all
+    // named after their lump or texture name. Composite textures shadow patches.
+    pub fn from_wad(wad: &Wad) -> Result<Self, WadError> {
+        let palette = match wad.lump_by_name("PLAYPAL") {
+            Some(palette) if palette.len() >= PALETTE_SIZE => &palette[..PALETTE_SIZE],
+            Some(palette) => return Err(WadError::BadLump { lump: "PLAYPAL", size: palette.len() }),
+            None => return Err(WadError::MissingLump { level: String::from("WAD"), lump: "PLAYPAL" }),
+        };
+        let mut textures = TextureSet::new();
+        // Flats
+        for index in wad_namespace(wad, "F") {
+            let lump = wad.lump(index);
+            if lump.len() == FLAT_SIZE * FLAT_SIZE {
+                textures.push(&wad.lumps[index].name, Texture::from_flat(lump, palette));
+            }
+        }
+        // Patches
+        for index in wad_namespace(wad, "P") {
+            if let Some(texture) = Texture::from_picture(wad.lump(index), palette) {
+                textures.push(&wad.lumps[index].name, texture);
+            }
+        }
+        // Sprites, transparent around the picture
+        for index in wad_namespace(wad, "S") {
+            if let Some(texture) = Texture::from_picture(wad.lump(index), palette) {
+                textures.push(&wad.lumps[index].name, texture);
+            }
+        }
+        // Composite textures
+        let patch_names: Vec<String> = match wad.lump_by_name("PNAMES") {
+            Some(lump) if lump.len() >= 4 => (0..read_i32(lump, 0).max(0) as usize)
+                .take_while(|i| 4 + (i + 1) * 8 <= lump.len())
+                .map(|i| read_name(lump, 4 + i * 8))
+                .collect(),
+            _ => vec![],
+        };
+        for lump in ["TEXTURE1", "TEXTURE2"] {
+            let definitions = match wad.lump_by_name(lump) {
+                Some(definitions) => definitions,
+                None => continue,
+            };
+            for (name, texture) in composite_textures(wad, lump, definitions, &patch_names, palette)? {
+                textures.push(&name, texture);
+            }
+        }
+        Ok(textures)
     }
 
     pub fn from(path: &str) -> Option<Self> {
@@ -112,6 +215,7 @@ impl TextureSet {
                 if let Some(extension) = path.extension() {
                     if extension == "tga" {
                         let raw_data = fs::read(&path).unwrap();
+                        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
                         let mut new_texture = Texture {
                             dimensions: Vec2 { x: 0, y: 0 },
                             channels: 0,
@@ -130,7 +234,7 @@ impl TextureSet {
                             match format {
                                 1 | 3 | 4 => {
                                     new_texture.channels = colors;
-                                    textures.set.push(new_texture)
+                                    textures.push(&name, new_texture)
                                 }
                                 2 => match from_rgba5551_to_rgba_32(&new_texture.data, 
                                                               new_texture.dimensions.x, 
@@ -138,7 +242,7 @@ impl TextureSet {
                                     Some(new_buffer) => {
                                         new_texture.data = new_buffer;
                                         new_texture.channels = colors;
-                                        textures.set.push(new_texture)
+                                        textures.push(&name, new_texture)
                                     },
                                     None => println!("{:?} does not supported", &path)
                                 },
@@ -154,3 +258,126 @@ impl TextureSet {
         return None;
     }
 }
+
+// Lumps between the X_START/X_END and XX_START/XX_END markers
+fn wad_namespace(wad: &Wad, prefix: &str) -> Vec<usize> {
+    let mut indices = Vec::new();
+    let mut inside = false;
+    for (index, lump) in wad.lumps.iter().enumerate() {
+        let name = lump.name.as_str();
+        if name == format!("{}_START", prefix) || name == format!("{}{}_START", prefix, prefix) {
+            inside = true;
+        } else if name == format!("{}_END", prefix) || name == format!("{}{}_END", prefix, prefix) {
+            inside = false;
+        } else if inside && lump.size > 0 {
+            indices.push(index);
+        }
+    }
+    indices
+}
+
+// Picture format, columns of posts of palette indices
+fn decode_picture(lump: &[u8]) -> Option<Picture> {
+    if lump.len() < 8 {
+        return None;
+    }
+    let width = read_i16(lump, 0);
+    let height = read_i16(lump, 2);
+    if width <= 0 || height <= 0 || lump.len() < 8 + width as usize * 4 {
+        return None;
+    }
+    let (width, height) = (width as usize, height as usize);
+    let mut indices = vec![None; width * height];
+    for x in 0..width {
+        let mut offset = read_i32(lump, 8 + x * 4) as usize;
+        loop {
+            let top = *lump.get(offset)?;
+            if top == END_OF_COLUMN {
+                break;
+            }
+            let length = *lump.get(offset + 1)? as usize;
+            // Skip the padding bytes around the post
+            let post = lump.get(offset + 3..offset + 3 + length)?;
+            for (i, index) in post.iter().enumerate() {
+                let y = top as usize + i;
+                if y < height {
+                    indices[y * width + x] = Some(*index);
+                }
+            }
+            offset += length + 4;
+        }
+    }
+    Some((width, height, indices))
+}
+
+fn composite_textures(
+    wad: &Wad,
+    lump: &'static str,
+    definitions: &[u8],
+    patch_names: &[String],
+    palette: &[u8]
+) -> Result<Vec<(String, Texture)>, WadError> {
+    let bad_lump = || WadError::BadLump { lump, size: definitions.len() };
+    // Count records of size bytes from start end inside of the lump, without overflowing
+    let fits = |start: usize, count: usize, size: usize| {
+        count
+            .checked_mul(size)
+            .and_then(|length| start.checked_add(length))
+            .is_some_and(|end| end <= definitions.len())
+    };
+    if !fits(0, 1, 4) {
+        return Err(bad_lump());
+    }
+    // Negative offsets and counts are malformed
+    let count = usize::try_from(read_i32(definitions, 0)).map_err(|_| bad_lump())?;
+    if !fits(4, count, 4) {
+        return Err(bad_lump());
+    }
+    // Decoded patches, shared between textures
+    let mut patches: HashMap<usize, Option<Picture>> = HashMap::new();
+    let mut textures = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset = usize::try_from(read_i32(definitions, 4 + i * 4)).map_err(|_| bad_lump())?;
+        if !fits(offset, 1, 22) {
+            return Err(bad_lump());
+        }
+        let name = read_name(definitions, offset);
+        let width = read_i16(definitions, offset + 12).max(0) as usize;
+        let height = read_i16(definitions, offset + 14).max(0) as usize;
+        let patch_count = usize::try_from(read_i16(definitions, offset + 20)).map_err(|_| bad_lump())?;
+        if width == 0 || height == 0 || !fits(offset + 22, patch_count, 10) {
+            return Err(bad_lump());
+        }
+        let mut indices: Vec<Option<u8>> = vec![None; width * height];
+        for p in 0..patch_count {
+            let record = offset + 22 + p * 10;
+            let origin_x = read_i16(definitions, record) as i32;
+            let origin_y = read_i16(definitions, record + 2) as i32;
+            let patch = read_i16(definitions, record + 4).max(0) as usize;
+            let decoded = patches.entry(patch).or_insert_with(|| {
+                patch_names
+                    .get(patch)
+                    .and_then(|name| wad.lump_by_name(name))
+                    .and_then(decode_picture)
+            });
+            let (patch_width, patch_height, patch_indices) = match decoded {
+                Some((patch_width, patch_height, patch_indices)) => (*patch_width, *patch_height, &*patch_indices),
+                None => continue,
+            };
+            for py in 0..patch_height {
+                for px in 0..patch_width {
+                    let x = origin_x + px as i32;
+                    let y = origin_y + py as i32;
+                    if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                        continue;
+                    }
+                    if let Some(index) = patch_indices[py * patch_width + px] {
+                        indices[y as usize * width + x as usize] = Some(index);
+                    }
+                }
+            }
+        }
+        textures.push((name, Texture::from_indexed(width, height, &indices, palette)));
+    }
+    Ok(textures)
+}
@@ -160,7 +160,7 @@ fn image_y_flip(
         let line_start = line_len * y;
         let line_end = line_len * (y + 1);
         let flipped_start = line_len * (image_height - y - 1);
-        let flipped_end = line_len * image_height;
+        let flipped_end = flipped_start + line_len;
         temp_line.copy_from_slice(&bytes[line_start..line_end]);
         temp_line_flip.copy_from_slice(&bytes[flipped_start..flipped_end]);
         bytes[line_start..line_end].copy_from_slice(&temp_line_flip);
@@ -272,3 +272,75 @@ pub fn decode_tga(
 
     true
 }
+
+fn encoder_rle(image_bytes_pixel: usize, buffer_in: &[u8], out_tga: &mut Vec<u8>) {
+    let pixels: Vec<&[u8]> = buffer_in.chunks_exact(image_bytes_pixel).collect();
+    let mut i = 0;
+    while i < pixels.len() {
+        // Length of the run starting at i
+        let mut run = 1;
+        while i + run < pixels.len() && run < 128 && pixels[i + run] == pixels[i] {
+            run += 1;
+        }
+        if run > 1 {
+			// 1XXX XXXX RLE chunk [ LEN | PIXEL ]
+            out_tga.push(0x80 | (run - 1) as u8);
+            out_tga.extend_from_slice(pixels[i]);
+            i += run;
+        } else {
+			// RAW chunk  [ LEN | PIXEL | PIXEL |... ], until the next run
+            let start = i;
+            while i < pixels.len() && i - start < 128 
+            && (i + 1 >= pixels.len() || pixels[i + 1] != pixels[i]) {
+                i += 1;
+            }
+            if i == start {
+                i += 1;
+            }
+            out_tga.push((i - start - 1) as u8);
+            for pixel in &pixels[start..i] {
+                out_tga.extend_from_slice(pixel);
+            }
+        }
+    }
+}
+
+// Encodes an RGB (3 channels) or RGBA (4 channels) image, rows stored top to bottom
+pub fn encode_tga(
+    out_tga: &mut Vec<u8>,
+    image: &[u8],
+    image_width: usize,
+    image_height: usize,
+    image_type: u8,
+    rle: bool,
+) -> bool {
+    let image_bytes_pixel = image_type as usize;
+    if (image_type != TgaColorType::RGB as u8 && image_type != TgaColorType::RGBA as u8)
+    || image.len() != image_width * image_height * image_bytes_pixel
+    || image_width > i16::MAX as usize 
+    || image_height > i16::MAX as usize {
+        return false;
+    }
+    // Header
+    let image_type = if rle { TgaImageType::RGBRLE } else { TgaImageType::RGB };
+    let alpha_bits: u8 = if image_bytes_pixel == 4 { 8 } else { 0 };
+    out_tga.clear();
+    out_tga.extend_from_slice(&[0, 0, image_type as u8]);
+    out_tga.extend_from_slice(&[0; 5]);
+    out_tga.extend_from_slice(&0i16.to_le_bytes());
+    out_tga.extend_from_slice(&0i16.to_le_bytes());
+    out_tga.extend_from_slice(&(image_width as i16).to_le_bytes());
+    out_tga.extend_from_slice(&(image_height as i16).to_le_bytes());
+    out_tga.push((image_bytes_pixel * 8) as u8);
+    out_tga.push(VERTICAL_FLIP_MASK | alpha_bits);
+    // Swap to BGR(A)
+    let mut data = image.to_vec();
+    rga_swap_r_and_b_24_32(&mut data, image_bytes_pixel, image_width, image_height);
+    // Pixels
+    if rle {
+        encoder_rle(image_bytes_pixel, &data, out_tga);
+    } else {
+        out_tga.extend_from_slice(&data);
+    }
+    true
+}
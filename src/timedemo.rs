@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+// Using, d3d
+use crate::consts;
+use crate::framebuffer::{Canvas, FrameBuffer};
+use crate::math::Vec3;
+use crate::player::Player;
+use crate::render::Render;
+// Using
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Scripted orbit around the player start, one frame a degree
+pub const ORBIT_FRAMES: usize = 360;
+const ORBIT_RADIUS: f32 = 8.0;
+
+// Frame times of a run, percentiles by nearest rank
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    pub frames: usize,
+    pub total: Duration,
+    pub average: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl FrameStats {
+    pub fn from_times(times: &[Duration]) -> Option<Self> {
+        if times.is_empty() {
+            return None;
+        }
+        let mut sorted = times.to_vec();
+        sorted.sort();
+        let percentile = |p: f32| sorted[((p * sorted.len() as f32).ceil() as usize).clamp(1, sorted.len()) - 1];
+        let total: Duration = sorted.iter().sum();
+        Some(FrameStats {
+            frames: sorted.len(),
+            total,
+            average: total / sorted.len() as u32,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        })
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.frames as f32 / self.total.as_secs_f32()
+    }
+}
+
+fn millis(duration: &Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} frames in {:.3} s, {:.1} fps", self.frames, self.total.as_secs_f32(), self.fps())?;
+        write!(f, "frame time (ms): average {:.3}, min {:.3}, max {:.3}, 50% {:.3}, 95% {:.3}, 99% {:.3}",
+               millis(&self.average), millis(&self.min), millis(&self.max),
+               millis(&self.p50), millis(&self.p95), millis(&self.p99))
+    }
+}
+
+// A full turn on a small circle around the start, looking outwards
+pub fn orbit(start: &Player, frames: usize) -> Vec<Player> {
+    (0..frames)
+        .map(|frame| {
+            let angle = (start.angle + 360.0 * frame as f32 / frames as f32).rem_euclid(360.0);
+            let mut player = Player::new_with_position_angles(start.position, angle, start.updown);
+            player.translate(Vec3::new(player.sin(), player.cos(), 0.0) * ORBIT_RADIUS);
+            player
+        })
+        .collect()
+}
+
+// Draws every camera of the path into a frame of the render size, as fast as it can
+pub fn run(render: &mut Render, path: &[Player]) -> Option<FrameStats> {
+    let mut canvas = Canvas::new(render.config.width as usize, render.config.height as usize);
+    let times: Vec<Duration> = path
+        .iter()
+        .map(|player| {
+            let start = Instant::now();
+            canvas.clear(consts::BACKGROUND_COLOR);
+            render.draw(&mut canvas, player);
+            start.elapsed()
+        })
+        .collect();
+    FrameStats::from_times(&times)
+}
@@ -0,0 +1,421 @@
+#![allow(dead_code)]
+// Using, d3d
+use crate::consts;
+use crate::math::{Vec2, Vec3};
+use crate::player::Player;
+use crate::texture::TextureSet;
+use crate::world::{Blend, Material, Sector, SectorHeight, TextureMapping, Thing as WorldThing, Wall, World};
+// Using
+use std::fmt;
+use std::fs;
+use std::io;
+
+// Thing type of the player 1 start
+const PLAYER_START: u16 = 1;
+// Front sprite of the things drawn, decorations and pickups. Monsters and
+// other things with rotation frames are left out.
+const THING_SPRITES: [(u16, &str); 20] = [
+    (2035, "BAR1A0"), (2028, "COLUA0"), (48, "ELECA0"), (30, "COL1A0"),
+    (31, "COL2A0"), (32, "COL3A0"), (33, "COL4A0"), (34, "CANDA0"),
+    (35, "CBRAA0"), (44, "TBLUA0"), (45, "TGRNA0"), (46, "TREDA0"),
+    (2011, "STIMA0"), (2012, "MEDIA0"), (2014, "BON1A0"), (2015, "BON2A0"),
+    (2018, "ARM1A0"), (2019, "ARM2A0"), (2007, "CLIPA0"), (2008, "SHELA0"),
+];
+// Sidedef index of a one-sided linedef
+const NO_SIDEDEF: u16 = 0xffff;
+// Walls and flats without a texture, white lit by the sector
+const UNTEXTURED: Material = Material::Color([0xff, 0xff, 0xff, 0xff]);
+
+// Size of the records of each lump
+const HEADER_SIZE: usize = 12;
+const DIRECTORY_SIZE: usize = 16;
+const THING_SIZE: usize = 10;
+const LINEDEF_SIZE: usize = 14;
+const SIDEDEF_SIZE: usize = 30;
+const VERTEX_SIZE: usize = 4;
+const SECTOR_SIZE: usize = 26;
+
+// Lumps following a level marker
+const LEVEL_LUMPS: [&str; 10] = [
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS",
+    "SSECTORS", "NODES", "SECTORS", "REJECT", "BLOCKMAP",
+];
+
+#[derive(Debug)]
+pub enum WadError {
+    Io(io::Error),
+    BadHeader,
+    BadDirectory,
+    LevelNotFound(String),
+    MissingLump { level: String, lump: &'static str },
+    BadLump { lump: &'static str, size: usize },
+    BadReference { lump: &'static str, index: usize },
+    NoPlayerStart,
+}
+
+impl fmt::Display for WadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WadError::Io(error) => write!(f, "I/O error: {}", error),
+            WadError::BadHeader => write!(f, "not an IWAD or PWAD file"),
+            WadError::BadDirectory => write!(f, "lump directory is outside of the file"),
+            WadError::LevelNotFound(level) => write!(f, "level {} not found", level),
+            WadError::MissingLump { level, lump } => write!(f, "level {} has no {} lump", level, lump),
+            WadError::BadLump { lump, size } => write!(f, "{} lump has an invalid size of {} bytes", lump, size),
+            WadError::BadReference { lump, index } => write!(f, "{} {} references a missing entry", lump, index),
+            WadError::NoPlayerStart => write!(f, "level has no player 1 start"),
+        }
+    }
+}
+
+impl std::error::Error for WadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WadError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WadError {
+    fn from(error: io::Error) -> Self {
+        WadError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lump {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+pub struct Wad {
+    pub data: Vec<u8>,
+    pub lumps: Vec<Lump>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Thing {
+    position: Vec2<i32>,
+    angle: i32,
+    kind: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Linedef {
+    v1: usize,
+    v2: usize,
+    right: u16,
+    left: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sidedef {
+    pub offset: Vec2<i32>,
+    pub upper: String,
+    pub lower: String,
+    pub middle: String,
+    pub sector: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WadSector {
+    pub height: SectorHeight,
+    pub floor: String,
+    pub ceiling: String,
+    pub light: u8,
+}
+
+pub(crate) fn read_i16(data: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+// Lump names are 8 bytes, padded with zeros
+pub(crate) fn read_name(data: &[u8], offset: usize) -> String {
+    data[offset..offset + 8]
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| byte.to_ascii_uppercase() as char)
+        .collect()
+}
+
+fn records<'a>(data: &'a [u8], lump: &'static str, size: usize) -> Result<std::slice::ChunksExact<'a, u8>, WadError> {
+    if !data.len().is_multiple_of(size) {
+        return Err(WadError::BadLump { lump, size: data.len() });
+    }
+    Ok(data.chunks_exact(size))
+}
+
+impl Wad {
+    pub fn from(path: &str) -> Result<Wad, WadError> {
+        Wad::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Wad, WadError> {
+        if data.len() < HEADER_SIZE || (&data[0..4] != b"IWAD" && &data[0..4] != b"PWAD") {
+            return Err(WadError::BadHeader);
+        }
+        let count = read_i32(&data, 4);
+        let directory = read_i32(&data, 8);
+        if count < 0 || directory < 0 {
+            return Err(WadError::BadDirectory);
+        }
+        let (count, directory) = (count as usize, directory as usize);
+        if data.len() < directory + count * DIRECTORY_SIZE {
+            return Err(WadError::BadDirectory);
+        }
+        let mut lumps = Vec::with_capacity(count);
+        for i in 0..count {
+            let entry = directory + i * DIRECTORY_SIZE;
+            let offset = read_i32(&data, entry) as usize;
+            let size = read_i32(&data, entry + 4) as usize;
+            if data.len() < offset.saturating_add(size) {
+                return Err(WadError::BadDirectory);
+            }
+            lumps.push(Lump { name: read_name(&data, entry + 8), offset, size });
+        }
+        Ok(Wad { data, lumps })
+    }
+
+    pub fn lump_index(&self, name: &str) -> Option<usize> {
+        // Later lumps override former ones, as in a PWAD
+        self.lumps.iter().rposition(|lump| lump.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn lump(&self, index: usize) -> &[u8] {
+        let lump = &self.lumps[index];
+        &self.data[lump.offset..lump.offset + lump.size]
+    }
+
+    pub fn lump_by_name(&self, name: &str) -> Option<&[u8]> {
+        self.lump_index(name).map(|index| self.lump(index))
+    }
+
+    pub fn levels(&self) -> Vec<String> {
+        self.lumps
+            .windows(2)
+            .filter(|pair| pair[1].name == "THINGS")
+            .map(|pair| pair[0].name.clone())
+            .collect()
+    }
+
+    fn level_lump(&self, level: &str, marker: usize, name: &'static str) -> Result<&[u8], WadError> {
+        self.lumps[marker + 1..]
+            .iter()
+            .take(LEVEL_LUMPS.len())
+            .take_while(|lump| LEVEL_LUMPS.contains(&lump.name.as_str()))
+            .position(|lump| lump.name == name)
+            .map(|index| self.lump(marker + 1 + index))
+            .ok_or(WadError::MissingLump { level: String::from(level), lump: name })
+    }
+
+    pub fn level_sidedefs(&self, level: &str) -> Result<Vec<Sidedef>, WadError> {
+        let marker = self.level_marker(level)?;
+        let mut sidedefs = Vec::new();
+        for record in records(self.level_lump(level, marker, "SIDEDEFS")?, "SIDEDEFS", SIDEDEF_SIZE)? {
+            sidedefs.push(Sidedef {
+                offset: Vec2::new(read_i16(record, 0) as i32, read_i16(record, 2) as i32),
+                upper: read_name(record, 4),
+                lower: read_name(record, 12),
+                middle: read_name(record, 20),
+                sector: read_u16(record, 28) as usize,
+            });
+        }
+        Ok(sidedefs)
+    }
+
+    pub fn level_sectors(&self, level: &str) -> Result<Vec<WadSector>, WadError> {
+        let marker = self.level_marker(level)?;
+        let mut sectors = Vec::new();
+        for record in records(self.level_lump(level, marker, "SECTORS")?, "SECTORS", SECTOR_SIZE)? {
+            sectors.push(WadSector {
+                height: SectorHeight::new(read_i16(record, 2) as i32, read_i16(record, 0) as i32),
+                floor: read_name(record, 4),
+                ceiling: read_name(record, 12),
+                light: read_u16(record, 20).min(255) as u8,
+            });
+        }
+        Ok(sectors)
+    }
+
+    fn level_marker(&self, level: &str) -> Result<usize, WadError> {
+        self.lump_index(level).ok_or_else(|| WadError::LevelNotFound(String::from(level)))
+    }
+
+    // Walls of each sector are grouped in a contiguous range, a linedef becomes
+    // a wall of the sector on its right side and, if two-sided, a reversed
+    // wall of the sector on its left side, each one a portal to the other.
+    // With a texture set, sidedef and flat names are resolved to textures by name
+    // and the things of THING_SPRITES are placed with their sprite.
+    pub fn load_level(&self, level: &str, textures: Option<&TextureSet>) -> Result<(World, Player), WadError> {
+        let marker = self.level_marker(level)?;
+        // Vertexes
+        let vertexes: Vec<Vec2<i32>> = records(self.level_lump(level, marker, "VERTEXES")?, "VERTEXES", VERTEX_SIZE)?
+            .map(|record| Vec2::new(read_i16(record, 0) as i32, read_i16(record, 2) as i32))
+            .collect();
+        // Linedefs
+        let linedefs: Vec<Linedef> = records(self.level_lump(level, marker, "LINEDEFS")?, "LINEDEFS", LINEDEF_SIZE)?
+            .map(|record| Linedef {
+                v1: read_u16(record, 0) as usize,
+                v2: read_u16(record, 2) as usize,
+                right: read_u16(record, 10),
+                left: read_u16(record, 12),
+            })
+            .collect();
+        // Things
+        let things: Vec<Thing> = records(self.level_lump(level, marker, "THINGS")?, "THINGS", THING_SIZE)?
+            .map(|record| Thing {
+                position: Vec2::new(read_i16(record, 0) as i32, read_i16(record, 2) as i32),
+                angle: read_u16(record, 4) as i32,
+                kind: read_u16(record, 6),
+            })
+            .collect();
+        let sidedefs = self.level_sidedefs(level)?;
+        let wad_sectors = self.level_sectors(level)?;
+        // Sector side of a linedef
+        let side_sector = |side: u16, index: usize| -> Result<Option<usize>, WadError> {
+            if side == NO_SIDEDEF {
+                return Ok(None);
+            }
+            match sidedefs.get(side as usize) {
+                Some(sidedef) if sidedef.sector < wad_sectors.len() => Ok(Some(sidedef.sector)),
+                _ => Err(WadError::BadReference { lump: "LINEDEFS", index }),
+            }
+        };
+        // Material of a texture of a side, stretched over the wall
+        let side_material = |name: &str, sector: usize, p1: &Vec2<i32>, p2: &Vec2<i32>| -> Option<Material> {
+            if name == "-" || name.is_empty() {
+                return None;
+            }
+            let texture = textures.and_then(|textures| textures.index(name).map(|texture| (textures, texture)));
+            Some(match texture {
+                Some((textures, texture)) => {
+                    let dimensions = textures.set[texture].dimensions;
+                    let length = p1.as_vec::<f32>().distance(&p2.as_vec::<f32>());
+                    let height = wad_sectors[sector].height.height() as f32;
+                    Material::Texture(TextureMapping {
+                        texture,
+                        uv: Vec2::new(repeat(length, dimensions.x), repeat(height, dimensions.y)),
+                        shade: 0
+                    })
+                },
+                None => UNTEXTURED,
+            })
+        };
+        // Side of a linedef, the middle texture of a portal is a masked layer in its opening
+        let side_wall = |side: u16, sector: usize, p1: &Vec2<i32>, p2: &Vec2<i32>, portal: Option<usize>| -> Wall {
+            let sidedef = &sidedefs[side as usize];
+            // Colors have no transparent texels, only a texture makes a layer
+            let middle = side_material(&sidedef.middle, sector, p1, p2)
+                .filter(|middle| portal.is_none() || matches!(middle, Material::Texture(_)));
+            let upper = side_material(&sidedef.upper, sector, p1, p2);
+            let lower = side_material(&sidedef.lower, sector, p1, p2);
+            match portal {
+                // Upper step from the upper texture, the lower step from the lower one,
+                // either of them without the other
+                Some(_) => Wall {
+                    portal,
+                    blend: if middle.is_some() { Blend::Masked } else { Blend::Opaque },
+                    middle,
+                    lower: lower.filter(|_| upper.is_some()),
+                    ..Wall::new_with_material(p1, p2, upper.or(lower).or(middle).unwrap_or(UNTEXTURED))
+                },
+                None => Wall::new_with_material(p1, p2, middle.or(upper).or(lower).unwrap_or(UNTEXTURED)),
+            }
+        };
+        let flat_material = |name: &str| -> Material {
+            match textures.and_then(|textures| textures.index(name)) {
+                Some(texture) => Material::Texture(TextureMapping {
+                    texture,
+                    uv: Vec2::new(1, 1),
+                    shade: 0
+                }),
+                None => UNTEXTURED,
+            }
+        };
+        // Regroup linedefs per sector
+        let mut sector_walls: Vec<Vec<Wall>> = vec![Vec::new(); wad_sectors.len()];
+        for (index, linedef) in linedefs.iter().enumerate() {
+            let (v1, v2) = match (vertexes.get(linedef.v1), vertexes.get(linedef.v2)) {
+                (Some(v1), Some(v2)) => (v1, v2),
+                _ => return Err(WadError::BadReference { lump: "LINEDEFS", index }),
+            };
+            let right = side_sector(linedef.right, index)?;
+            let left = side_sector(linedef.left, index)?;
+            // Two-sided linedefs are portals to the sector on the other side
+            if let Some(sector) = right {
+                sector_walls[sector].push(side_wall(linedef.right, sector, v1, v2, left));
+            }
+            if let Some(sector) = left {
+                sector_walls[sector].push(side_wall(linedef.left, sector, v2, v1, right));
+            }
+        }
+        // Build world
+        let mut walls: Vec<Wall> = Vec::new();
+        let mut sectors: Vec<Sector> = Vec::new();
+        for (wad_sector, group) in wad_sectors.iter().zip(sector_walls) {
+            let range = Vec2::new(walls.len() as i32, (walls.len() + group.len()) as i32);
+            sectors.push(Sector {
+                light: wad_sector.light,
+                ..Sector::new_with_materials(&range, &wad_sector.height, [
+                    flat_material(&wad_sector.ceiling),
+                    flat_material(&wad_sector.floor),
+                ])
+            });
+            walls.extend(group);
+        }
+        let mut world = World { walls, sectors, things: vec![] };
+        // Things standing on the floor, as tall as their sprite
+        if let Some(textures) = textures {
+            for thing in &things {
+                let texture = THING_SPRITES
+                    .iter()
+                    .find(|(kind, _)| *kind == thing.kind)
+                    .and_then(|(_, sprite)| textures.index(sprite));
+                let (texture, sector) = match (texture, world.sector_at(&thing.position.as_vec::<f32>())) {
+                    (Some(texture), Some(sector)) => (texture, sector),
+                    _ => continue,
+                };
+                world.things.push(WorldThing {
+                    facing: (450 - thing.angle).rem_euclid(360) as f32,
+                    ..WorldThing::new(
+                        &Vec3::new_vec2_z(&thing.position, world.sectors[sector].height.bottom),
+                        textures.set[texture].dimensions.y as i32,
+                        texture
+                    )
+                });
+            }
+        }
+        // Player start
+        let start = things
+            .iter()
+            .find(|thing| thing.kind == PLAYER_START)
+            .ok_or(WadError::NoPlayerStart)?;
+        let floor = match world.sector_at(&start.position.as_vec::<f32>()) {
+            Some(sector) => world.sectors[sector].height.bottom,
+            None => 0,
+        };
+        // Doom angles are counterclockwise from east, the player's are clockwise from north.
+        // Standing on the floor, at the eye height the player keeps while walking
+        let player = Player::new_with_position_angles(
+            Vec3::new_vec2_z(&start.position, floor + consts::EYE_HEIGHT as i32),
+            (450 - start.angle).rem_euclid(360) as f32,
+            0.0
+        );
+        Ok((world, player))
+    }
+}
+
+// Number of times a texture is repeated along a size in map units
+fn repeat(size: f32, texels: usize) -> i32 {
+    ((size / texels.max(1) as f32).round() as i32).max(1)
+}
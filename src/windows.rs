@@ -1,14 +1,12 @@
 
 #![allow(dead_code)]
-// Using, d3d
-use crate::math::Vec2;
 // Using
 use pixels::{Error, Pixels, SurfaceTexture};
 use winit::{
     dpi::LogicalSize,
-    error::OsError,
+    error::{ExternalError, OsError},
     event_loop::EventLoop,
-    window::Window,
+    window::{CursorGrabMode, Window},
     window::WindowBuilder,
 };
 
@@ -38,31 +36,13 @@ pub fn pixes_from_size(window: &Window, width: u32, height: u32) -> Result<Pixel
     Pixels::new(width, height, surface_texture)
 }
 
-pub fn clear_background(pixels: &mut Pixels, color: [u8; 4]) {
-    let frame = pixels.frame_mut();
-    let size = (frame.len() / color.len()) as usize;
-    frame.copy_from_slice(&color.repeat(size));
-}
-
-pub fn draw_pixel(
-    pixels: &mut Pixels,
-    position: &Vec2<usize>,
-    color: &[u8],
-) {
-    let size = pixels.texture().size();
-    let channels = pixels.texture().format().block_size(None).unwrap() as usize;
-    if position.x >= size.width as usize|| position.y >= size.height as usize {
-        return;
-    }
-    let frame = pixels.frame_mut();
-    let row_size = (size.width as usize) * channels; // 4 colors per byte
-    let offset: usize = (size.height as usize - position.y - 1) * row_size + position.x * channels;
-    let mut ptr = frame.as_mut_ptr();
-    unsafe {
-        ptr = ptr.add(offset);
-        for channel in color.iter() {
-            (*ptr) = *channel;
-            ptr = ptr.add(1);
-        }
+// Hides the cursor and keeps it in the window, locked in place where supported
+pub fn capture_cursor(window: &Window, capture: bool) -> Result<(), ExternalError> {
+    window.set_cursor_visible(!capture);
+    if capture {
+        window.set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
     }
 }
@@ -1,15 +1,26 @@
 #![allow(dead_code)]
 // Using, d3d
-use crate::math::Vec2;
+use crate::math::{Vec2, Vec3};
+use crate::texture::TextureSet;
+// Using
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Clone, Copy)]
+// Passes of World::slide, a corner needs one for each wall
+const SLIDE_ITERATIONS: usize = 4;
+
+// Light of a sector drawn as bright at every distance
+pub const FULL_LIGHT: u8 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct TextureMapping {
     pub texture: usize,
     pub uv: Vec2<i32>,
     pub shade: u8
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Material {
     Color([u8; 4]),
     Texture(TextureMapping)
@@ -24,10 +35,42 @@ impl Material {
     }
 }
 
+// How the texels of a wall cover what is behind it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Blend {
+    #[default]
+    Opaque,
+    // Texels with an alpha of zero are not drawn, for grates and fences
+    Masked,
+    // Texels mixed by their alpha times the opacity, for windows
+    Translucent(u8),
+}
+
+impl Blend {
+    pub fn is_opaque(&self) -> bool {
+        *self == Blend::Opaque
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Wall {
     pub point1: Vec2<i32>,
     pub point2: Vec2<i32>,
     pub material: Material,
+    // Sector on the other side, the wall is drawn only above and below it
+    #[serde(default)]
+    pub portal: Option<usize>,
+    // A portal that is not opaque is drawn in its opening too, over the sector behind
+    #[serde(default, skip_serializing_if = "Blend::is_opaque")]
+    pub blend: Blend,
+    // Drawn in the opening instead of the material of the steps, like the
+    // middle texture of a Doom linedef
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub middle: Option<Material>,
+    // Drawn on the lower step instead of the material, like the lower
+    // texture of a Doom linedef
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lower: Option<Material>,
 }
 
 impl Wall {
@@ -36,6 +79,10 @@ impl Wall {
             point1: point1.clone(),
             point2: point2.clone(),
             material: Material::Color([0xff, 0xff, 0xff, 0xff]),
+            portal: None,
+            blend: Blend::Opaque,
+            middle: None,
+            lower: None,
         }
     }
     pub fn new_with_material(point1: &Vec2<i32>, point2: &Vec2<i32>, material: Material) -> Self {
@@ -43,11 +90,36 @@ impl Wall {
             point1: point1.clone(),
             point2: point2.clone(),
             material: material,
+            portal: None,
+            blend: Blend::Opaque,
+            middle: None,
+            lower: None,
         }
     }
+    pub fn new_portal(point1: &Vec2<i32>, point2: &Vec2<i32>, material: Material, sector: usize) -> Self {
+        Wall {
+            point1: *point1,
+            point2: *point2,
+            material,
+            portal: Some(sector),
+            blend: Blend::Opaque,
+            middle: None,
+            lower: None,
+        }
+    }
+
+    // Material of the opening of a portal
+    pub fn opening(&self) -> &Material {
+        self.middle.as_ref().unwrap_or(&self.material)
+    }
+
+    // Material of the step below the opening of a portal
+    pub fn lower_step(&self) -> &Material {
+        self.lower.as_ref().unwrap_or(&self.material)
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct SectorHeight {
     pub top: i32,
     pub bottom: i32 
@@ -71,10 +143,19 @@ impl SectorHeight {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Sector {
     pub wall: Vec2<i32>,
     pub height: SectorHeight,
-    pub material: [Material; 2]
+    pub material: [Material; 2],
+    // From 0, dark, to 255, like a Doom sector. Below full light the sector
+    // gets darker with distance.
+    #[serde(default = "full_light")]
+    pub light: u8
+}
+
+fn full_light() -> u8 {
+    FULL_LIGHT
 }
 
 impl Sector {
@@ -86,7 +167,8 @@ impl Sector {
             material: [
                 Material::Color([0xff,0xff,0xff,0xff]),
                 Material::Color([0xff,0xff,0xff,0xff])
-            ]
+            ],
+            light: FULL_LIGHT
         }
     }
 
@@ -97,7 +179,8 @@ impl Sector {
             material: [
                 Material::Color(colors[0]),
                 Material::Color(colors[1])
-            ]
+            ],
+            light: FULL_LIGHT
         }
     }
 
@@ -108,7 +191,8 @@ impl Sector {
             material: [
                 material.clone(),
                 material.clone(),
-            ]
+            ],
+            light: FULL_LIGHT
         }
     }
 
@@ -116,12 +200,246 @@ impl Sector {
         Sector {
             wall: wall.clone(),
             height: height.clone(),
-            material: materials
+            material: materials,
+            light: FULL_LIGHT
         }
     }
 }
 
+// Object drawn as a sprite facing the player, its bottom at position.z
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Thing {
+    pub position: Vec3<i32>,
+    pub height: i32,
+    pub texture: usize,
+    // Degrees clockwise from north, like the player. Sprites have no rotation
+    // frames, they are drawn the same from every side.
+    #[serde(default)]
+    pub facing: f32,
+}
+
+impl Thing {
+    pub fn new(position: &Vec3<i32>, height: i32, texture: usize) -> Self {
+        Thing {
+            position: *position,
+            height,
+            texture,
+            facing: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct World {
     pub walls: Vec<Wall>,
     pub sectors: Vec<Sector>,
+    #[serde(default)]
+    pub things: Vec<Thing>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    WallRange { sector: usize, start: i32, end: i32, walls: usize },
+    SectorHeight { sector: usize, top: i32, bottom: i32 },
+    SectorTexture { sector: usize, material: usize, texture: usize },
+    WallTexture { sector: Option<usize>, wall: usize, texture: usize },
+    OpenLoop { sector: usize, point: Vec2<i32> },
+    Portal { sector: Option<usize>, wall: usize, neighbor: usize },
+    ThingTexture { thing: usize, texture: usize },
+    ThingHeight { thing: usize, height: i32 },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::WallRange { sector, start, end, walls } =>
+                write!(f, "sector {}: wall range {}..{} is outside of the {} walls", sector, start, end, walls),
+            ValidationError::SectorHeight { sector, top, bottom } =>
+                write!(f, "sector {}: top {} is not above bottom {}", sector, top, bottom),
+            ValidationError::SectorTexture { sector, material, texture } =>
+                write!(f, "sector {}: {} texture {} does not exist", sector,
+                       if *material == 0 { "top" } else { "bottom" }, texture),
+            ValidationError::WallTexture { sector: Some(sector), wall, texture } =>
+                write!(f, "sector {}, wall {}: texture {} does not exist", sector, wall, texture),
+            ValidationError::WallTexture { sector: None, wall, texture } =>
+                write!(f, "wall {}: texture {} does not exist", wall, texture),
+            ValidationError::OpenLoop { sector, point } =>
+                write!(f, "sector {}: walls do not form a closed loop at ({}, {})", sector, point.x, point.y),
+            ValidationError::Portal { sector: Some(sector), wall, neighbor } =>
+                write!(f, "sector {}, wall {}: portal to sector {} is not a valid neighbor", sector, wall, neighbor),
+            ValidationError::Portal { sector: None, wall, neighbor } =>
+                write!(f, "wall {}: portal to sector {} is not a valid neighbor", wall, neighbor),
+            ValidationError::ThingTexture { thing, texture } =>
+                write!(f, "thing {}: texture {} does not exist", thing, texture),
+            ValidationError::ThingHeight { thing, height } =>
+                write!(f, "thing {}: height {} is not positive", thing, height),
+        }
+    }
+}
+
+impl World {
+    pub fn validate(&self, textures: &TextureSet) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let texture_exists = |material: &Material| match material {
+            Material::Texture(map) => map.texture < textures.set.len(),
+            Material::Color(_) => true,
+        };
+        let texture_of = |material: &Material| match material {
+            Material::Texture(map) => map.texture,
+            Material::Color(_) => 0,
+        };
+        // Walls owned by a sector, to report the sector of a bad wall
+        let mut owners: Vec<Option<usize>> = vec![None; self.walls.len()];
+        for (sector_id, sector) in self.sectors.iter().enumerate() {
+            // Heights
+            if sector.height.top <= sector.height.bottom {
+                errors.push(ValidationError::SectorHeight {
+                    sector: sector_id,
+                    top: sector.height.top,
+                    bottom: sector.height.bottom,
+                });
+            }
+            // Floor and ceiling textures
+            for (material_id, material) in sector.material.iter().enumerate() {
+                if !texture_exists(material) {
+                    errors.push(ValidationError::SectorTexture {
+                        sector: sector_id,
+                        material: material_id,
+                        texture: texture_of(material),
+                    });
+                }
+            }
+            // Wall range
+            let walls = match self.sector_walls(sector) {
+                Some(walls) => walls,
+                None => {
+                    errors.push(ValidationError::WallRange {
+                        sector: sector_id,
+                        start: sector.wall.x,
+                        end: sector.wall.y,
+                        walls: self.walls.len(),
+                    });
+                    continue;
+                }
+            };
+            for owner in &mut owners[sector.wall.x as usize..sector.wall.y as usize] {
+                owner.get_or_insert(sector_id);
+            }
+            // Closed loop, each point must start as many walls as it ends
+            let mut balance: HashMap<(i32, i32), i32> = HashMap::new();
+            for wall in walls {
+                *balance.entry((wall.point1.x, wall.point1.y)).or_insert(0) += 1;
+                *balance.entry((wall.point2.x, wall.point2.y)).or_insert(0) -= 1;
+            }
+            let mut open: Vec<(i32, i32)> = balance
+                .into_iter()
+                .filter(|(_, count)| *count != 0)
+                .map(|(point, _)| point)
+                .collect();
+            open.sort();
+            for (x, y) in open {
+                errors.push(ValidationError::OpenLoop { sector: sector_id, point: Vec2::new(x, y) });
+            }
+        }
+        // Wall textures and portals
+        for (wall_id, wall) in self.walls.iter().enumerate() {
+            if let Some(neighbor) = wall.portal {
+                if neighbor >= self.sectors.len() || owners[wall_id] == Some(neighbor) {
+                    errors.push(ValidationError::Portal { sector: owners[wall_id], wall: wall_id, neighbor });
+                }
+            }
+            for material in [Some(&wall.material), wall.middle.as_ref(), wall.lower.as_ref()].into_iter().flatten() {
+                if !texture_exists(material) {
+                    errors.push(ValidationError::WallTexture {
+                        sector: owners[wall_id],
+                        wall: wall_id,
+                        texture: texture_of(material),
+                    });
+                }
+            }
+        }
+        // Sprites
+        for (thing_id, thing) in self.things.iter().enumerate() {
+            if textures.set.len() <= thing.texture {
+                errors.push(ValidationError::ThingTexture { thing: thing_id, texture: thing.texture });
+            }
+            if thing.height <= 0 {
+                errors.push(ValidationError::ThingHeight { thing: thing_id, height: thing.height });
+            }
+        }
+        errors
+    }
+
+    pub fn sector_at(&self, point: &Vec2<f32>) -> Option<usize> {
+        self.sectors.iter().position(|sector| match self.sector_walls(sector) {
+            Some(walls) => contains(walls, point),
+            None => false,
+        })
+    }
+
+    // End of a move of a circle, pushed out of the solid walls it would overlap so
+    // that it slides along them
+    pub fn slide<F: Fn(&Wall) -> bool>(&self, position: &Vec2<f32>, direction: &Vec2<f32>, radius: f32, solid: F) -> Vec2<f32> {
+        let mut target = *position + *direction;
+        for _ in 0..SLIDE_ITERATIONS {
+            let mut pushed = false;
+            for wall in self.walls.iter().filter(|wall| solid(wall)) {
+                let closest = closest_point(wall, &target);
+                let distance = target.distance(&closest);
+                if radius <= distance {
+                    continue;
+                }
+                // Away from the wall, on the side the move started from
+                let mut normal = if distance > 0.0 {
+                    (target - closest) * (1.0 / distance)
+                } else {
+                    (*position - closest).normalize()
+                };
+                if normal.dot(&(*position - closest)) < 0.0 {
+                    normal = normal * -1.0;
+                }
+                target = closest + normal * radius;
+                pushed = true;
+            }
+            if !pushed {
+                break;
+            }
+        }
+        target
+    }
+
+    pub fn sector_walls(&self, sector: &Sector) -> Option<&[Wall]> {
+        if sector.wall.x < 0 || sector.wall.y < sector.wall.x || self.walls.len() < sector.wall.y as usize {
+            return None;
+        }
+        Some(&self.walls[sector.wall.x as usize..sector.wall.y as usize])
+    }
+}
+
+// Point of the wall nearest to the given one
+fn closest_point(wall: &Wall, point: &Vec2<f32>) -> Vec2<f32> {
+    let p1 = wall.point1.as_vec::<f32>();
+    let p2 = wall.point2.as_vec::<f32>();
+    let line = p2 - p1;
+    let length = line.dot(&line);
+    if length == 0.0 {
+        return p1;
+    }
+    p1 + line * ((*point - p1).dot(&line) / length).clamp(0.0, 1.0)
+}
+
+// Even-odd rule, holes are walls of the same sector
+fn contains(walls: &[Wall], point: &Vec2<f32>) -> bool {
+    let mut inside = false;
+    for wall in walls {
+        let p1 = wall.point1.as_vec::<f32>();
+        let p2 = wall.point2.as_vec::<f32>();
+        if (p1.y > point.y) != (p2.y > point.y) {
+            let x = p1.x + (point.y - p1.y) * (p2.x - p1.x) / (p2.y - p1.y);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
 }
@@ -0,0 +1,30 @@
+use rust_doom_demo::bindings::{Action, Bindings, Preset};
+use winit::event::VirtualKeyCode;
+
+#[test]
+fn presets() {
+    let standard = Bindings::preset(Preset::Standard);
+    let classic = Bindings::preset(Preset::Classic);
+    assert_eq!(standard.keys(Action::MoveNorth), [VirtualKeyCode::W]);
+    assert!(standard.keys(Action::MoveForward).is_empty());
+    assert_eq!(classic.keys(Action::MoveForward), [VirtualKeyCode::W]);
+    assert!(classic.keys(Action::MoveNorth).is_empty());
+    // Everything else is shared
+    for action in [Action::TurnLeft, Action::LookUp, Action::FlyUp, Action::ToggleNoclip, Action::Quit] {
+        assert_eq!(standard.keys(action), classic.keys(action));
+    }
+}
+
+#[test]
+fn config_file() {
+    let bindings = Bindings::from("assets/bindings/esdf.ron", Preset::Standard).unwrap();
+    assert_eq!(bindings.keys(Action::MoveForward), [VirtualKeyCode::E, VirtualKeyCode::Up]);
+    // Actions left out keep the keys of the preset
+    assert_eq!(bindings.keys(Action::Quit), [VirtualKeyCode::Escape]);
+    assert!(bindings.keys(Action::MoveNorth).is_empty());
+    // Without a preset the given one is used
+    let bindings = Bindings::from_ron("(keys: { Quit: [Q] })", Preset::Classic).unwrap();
+    assert_eq!(bindings.keys(Action::Quit), [VirtualKeyCode::Q]);
+    assert_eq!(bindings.keys(Action::MoveForward), [VirtualKeyCode::W]);
+    assert!(Bindings::from_ron("(keys: { Jump: [Space] })", Preset::Classic).is_err());
+}
@@ -0,0 +1,67 @@
+use rust_doom_demo::bsp::Bsp;
+use rust_doom_demo::consts;
+use rust_doom_demo::framebuffer::{Canvas, FrameBuffer};
+use rust_doom_demo::map::Map;
+use rust_doom_demo::player::Player;
+use rust_doom_demo::render::{Render, RenderConfig, RenderMode};
+use rust_doom_demo::texture::TextureSet;
+use std::rc::Rc;
+
+#[test]
+fn segs_cover_walls() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let bsp = Bsp::build(&map.world);
+    for (index, wall) in map.world.walls.iter().enumerate() {
+        let covered: f32 = bsp.segs.iter()
+            .filter(|seg| seg.wall == index)
+            .map(|seg| seg.u.y - seg.u.x)
+            .sum();
+        assert!((covered - 1.0).abs() < 1e-4, "wall {} {:?} covered {}", index, wall, covered);
+    }
+}
+
+#[test]
+fn leaves_are_convex() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let bsp = Bsp::build(&map.world);
+    for leaf in bsp.leaves.iter() {
+        let segs = &bsp.segs[leaf.segs.x..leaf.segs.y];
+        for seg in segs {
+            let direction = (seg.point2 - seg.point1).normalize();
+            for other in segs {
+                for point in [other.point1, other.point2] {
+                    assert!(direction.cross(&(point - seg.point1)) > -0.01, "{:?} behind {:?}", other, seg);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn ron_keeps_bsp() {
+    let mut map = Map::from("assets/rooms.ron").unwrap();
+    map.build_bsp();
+    let reloaded = Map::from_ron(&map.to_ron().unwrap()).unwrap();
+    assert_eq!(map.bsp, reloaded.bsp);
+}
+
+#[test]
+fn bsp_matches_portals() {
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let bsp = Rc::new(Bsp::build(&map.world));
+    let mut portals = Render::new(Rc::clone(&map.world), Rc::clone(&textures), RenderConfig::default());
+    let mut walk = Render::new(Rc::clone(&map.world), textures, RenderConfig::default()).with_bsp(bsp);
+    assert_eq!(walk.mode, RenderMode::Bsp);
+    let start = *map.player.borrow();
+    for angle in (0..360).step_by(30) {
+        let player = Player::new_with_position_angles(start.position, angle as f32, start.updown);
+        let [left, right] = [&mut portals, &mut walk].map(|render| {
+            let mut canvas = Canvas::new(consts::WIDTH as usize, consts::HEIGHT as usize);
+            canvas.clear(consts::BACKGROUND_COLOR);
+            render.draw(&mut canvas, &player);
+            canvas
+        });
+        assert!(left.buffer() == right.buffer(), "angle {}", angle);
+    }
+}
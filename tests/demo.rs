@@ -0,0 +1,64 @@
+use rust_doom_demo::clock::Clock;
+use rust_doom_demo::consts;
+use rust_doom_demo::demo::{Demo, DemoError};
+use rust_doom_demo::map::Map;
+use rust_doom_demo::math::Vec2;
+use rust_doom_demo::player::PlayerInput;
+use std::time::Duration;
+
+// Walks east turning with the mouse, takes off and lands again
+fn script(tick: u32) -> PlayerInput {
+    PlayerInput {
+        forward: (tick < 60) as i8,
+        strafe: if tick % 20 < 10 { 1 } else { -1 },
+        fly: (40..45).contains(&tick) as i8,
+        mouse: Vec2::new(((tick % 7) as f32 - 3.0) * 0.37, (tick % 5) as f32 * 0.11),
+        toggle_fly: tick == 70,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn playback_matches_recording() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let clock = Clock::new(consts::TICK_RATE, Duration::from_secs_f32(consts::MAX_FRAME_TIME));
+    let mut player = *map.player.borrow();
+    let mut demo = Demo::new(player, consts::TICK_RATE);
+    let mut path = vec![player];
+    for tick in 0..100 {
+        let input = script(tick);
+        demo.record(&input);
+        player.tick(&map.world, &input, clock.delta());
+        path.push(player);
+    }
+    assert_eq!(demo.len(), 100);
+    // Through the file, exactly the same path
+    let demo = Demo::from_ron(&demo.to_ron().unwrap()).unwrap();
+    assert_eq!(demo.path(&map.world), path);
+    assert!(!path.last().unwrap().flying);
+}
+
+#[test]
+fn same_inputs_are_stored_once() {
+    let map = Map::from("assets/box.map").unwrap();
+    let mut demo = Demo::new(*map.player.borrow(), consts::TICK_RATE);
+    let walk = PlayerInput { forward: 1, ..Default::default() };
+    for _ in 0..1000 {
+        demo.record(&walk);
+    }
+    assert_eq!(demo.len(), 1000);
+    assert_eq!(demo.inputs().count(), 1000);
+    assert!(demo.to_ron().unwrap().len() < 1000);
+}
+
+#[test]
+fn rejects_other_versions_and_rates() {
+    let map = Map::from("assets/box.map").unwrap();
+    let mut demo = Demo::new(*map.player.borrow(), consts::TICK_RATE * 2);
+    demo.version += 1;
+    assert!(matches!(Demo::from_ron(&demo.to_ron().unwrap()), Err(DemoError::Version { .. })));
+    demo.version -= 1;
+    let path = std::env::temp_dir().join("rust-doom-demo-rate.ron");
+    demo.save(path.to_str().unwrap()).unwrap();
+    assert!(matches!(Demo::from(path.to_str().unwrap(), consts::TICK_RATE), Err(DemoError::TickRate { .. })));
+}
@@ -0,0 +1,116 @@
+// Renders every map of assets/ from a few fixed poses and compares the frames
+// with the reference images in tests/golden, the legacy maps with BSP too. Run with UPDATE_GOLDEN=1 to
+// write the references again after an intended change of the renderer.
+use rust_doom_demo::bsp::Bsp;
+use rust_doom_demo::consts;
+use rust_doom_demo::framebuffer::{save_frame, Canvas, FrameBuffer};
+use rust_doom_demo::map::Map;
+use rust_doom_demo::math::Vec3;
+use rust_doom_demo::player::Player;
+use rust_doom_demo::render::{Render, RenderConfig};
+use rust_doom_demo::texture::TextureSet;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+// Max difference of a channel
+const TOLERANCE: u8 = 2;
+const GOLDEN_DIR: &str = "tests/golden";
+const DIFF_DIR: &str = "target/golden";
+
+struct Pose {
+    name: &'static str,
+    offset: Vec3<i32>,
+    angle: i32,
+    updown: i32,
+}
+
+const POSES: [Pose; 4] = [
+    Pose { name: "start", offset: Vec3 { x: 0, y: 0, z: 0 }, angle: 0, updown: 0 },
+    Pose { name: "turn", offset: Vec3 { x: 0, y: 0, z: 0 }, angle: 45, updown: 0 },
+    Pose { name: "look", offset: Vec3 { x: 0, y: 0, z: 0 }, angle: 0, updown: -20 },
+    Pose { name: "above", offset: Vec3 { x: 0, y: 0, z: 30 }, angle: -30, updown: 15 },
+];
+
+fn render(render: &mut Render, player: &Player) -> Canvas {
+    let mut canvas = Canvas::new(consts::WIDTH as usize, consts::HEIGHT as usize);
+    canvas.clear(consts::BACKGROUND_COLOR);
+    render.draw(&mut canvas, player);
+    canvas
+}
+
+// Number of pixels out of tolerance and an image of the differences
+fn compare(actual: &Canvas, expected: &Canvas) -> (usize, Canvas) {
+    let mut diff = Canvas::new(actual.dimensions.x, actual.dimensions.y);
+    let mut mismatches = 0;
+    for ((a, e), d) in actual.data.chunks_exact(4)
+        .zip(expected.data.chunks_exact(4))
+        .zip(diff.data.chunks_exact_mut(4)) {
+        let delta: Vec<u8> = a.iter().zip(e).map(|(a, e)| a.abs_diff(*e)).collect();
+        if delta.iter().any(|delta| *delta > TOLERANCE) {
+            mismatches += 1;
+            d.copy_from_slice(&[0xff, delta[0].max(delta[1]).max(delta[2]), 0, 0xff]);
+        } else {
+            d.copy_from_slice(&[a[0] / 4, a[1] / 4, a[2] / 4, 0xff]);
+        }
+    }
+    (mismatches, diff)
+}
+
+#[test]
+fn golden_images() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    let mut paths: Vec<_> = fs::read_dir("assets")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "map" || extension == "ron"))
+        .collect();
+    paths.sort();
+    fs::create_dir_all(GOLDEN_DIR).unwrap();
+    fs::create_dir_all(DIFF_DIR).unwrap();
+    let mut failures = Vec::new();
+    for path in paths {
+        let map = Map::from(path.to_str().unwrap()).unwrap();
+        let start = *map.player.borrow();
+        let mut renderers = vec![("", Render::new(Rc::clone(&map.world), Rc::clone(&textures), RenderConfig::default()))];
+        // The player of legacy maps is out of every sector, where BSP paints the segs
+        if path.extension().is_some_and(|extension| extension == "map") {
+            let bsp = Rc::new(Bsp::build(&map.world));
+            renderers.push(("-bsp", Render::new(Rc::clone(&map.world), Rc::clone(&textures), RenderConfig::default()).with_bsp(bsp)));
+        }
+        for (suffix, renderer) in renderers.iter_mut() {
+            for pose in POSES.iter() {
+                let player = Player::new_with_position_angles(
+                    start.position + pose.offset,
+                    (start.angle + pose.angle as f32).rem_euclid(360.0),
+                    start.updown + pose.updown as f32,
+                );
+                let actual = render(renderer, &player);
+                let name = format!("{}-{}{}", path.file_stem().unwrap().to_string_lossy(), pose.name, suffix);
+                let golden = format!("{}/{}.tga", GOLDEN_DIR, name);
+                if update {
+                    save_frame(&actual, &golden).unwrap();
+                    continue;
+                }
+                if !Path::new(&golden).exists() {
+                    failures.push(format!("{}: no reference image, run with UPDATE_GOLDEN=1", name));
+                    continue;
+                }
+                let expected = Canvas::load(&golden).unwrap();
+                if expected.dimensions != actual.dimensions {
+                    failures.push(format!("{}: size {:?} instead of {:?}", name, actual.dimensions, expected.dimensions));
+                    continue;
+                }
+                let (mismatches, diff) = compare(&actual, &expected);
+                if mismatches > 0 {
+                    let diff_path = format!("{}/{}-diff.tga", DIFF_DIR, name);
+                    save_frame(&diff, &diff_path).unwrap();
+                    save_frame(&actual, &format!("{}/{}-actual.tga", DIFF_DIR, name)).unwrap();
+                    failures.push(format!("{}: {} pixels differ, see {}", name, mismatches, diff_path));
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,159 @@
+use rust_doom_demo::map::{Map, MapError, MapLine};
+use rust_doom_demo::math::Vec3;
+use rust_doom_demo::player::Player;
+use rust_doom_demo::world::{Blend, Material, FULL_LIGHT};
+use std::fs;
+use std::rc::Rc;
+
+#[test]
+fn round_trip_assets() {
+    let mut count = 0;
+    for entry in fs::read_dir("assets").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "map") {
+            continue;
+        }
+        let map = Map::from(path.to_str().unwrap()).unwrap();
+        let text = map.to_string().unwrap();
+        let reloaded = Map::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(map.world, reloaded.world, "{:?}", path);
+        assert_eq!(*map.player.borrow(), *reloaded.player.borrow(), "{:?}", path);
+        assert_eq!(text, reloaded.to_string().unwrap(), "{:?}", path);
+        count += 1;
+    }
+    assert!(count > 0);
+}
+
+#[test]
+fn ron_round_trip_assets() {
+    for entry in fs::read_dir("assets").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "map") {
+            continue;
+        }
+        let map = Map::from(path.to_str().unwrap()).unwrap();
+        let reloaded = Map::from_ron(&map.to_ron().unwrap()).unwrap();
+        assert_eq!(map.world, reloaded.world, "{:?}", path);
+        assert_eq!(*map.player.borrow(), *reloaded.player.borrow(), "{:?}", path);
+    }
+}
+
+fn parse(text: &str) -> Result<Map, MapError> {
+    Map::from_reader(text.as_bytes())
+}
+
+#[test]
+fn malformed_maps() {
+    // Line and column of the bad token
+    assert!(matches!(
+        parse("1\n0 4  4x 0\n"),
+        Err(MapError::BadInteger { line: 2, column: 6, ref token }) if token == "4x"
+    ));
+    assert!(matches!(
+        parse("0\n1\n0 0 32 0 255 0 z\n"),
+        Err(MapError::BadInteger { line: 3, column: 16, ref token }) if token == "z"
+    ));
+    // Counts are not negative and fit an i32
+    assert!(matches!(
+        parse("-1\n"),
+        Err(MapError::BadInteger { line: 1, column: 1, ref token }) if token == "-1"
+    ));
+    assert!(matches!(
+        parse("0\n99999999999\n"),
+        Err(MapError::BadInteger { line: 2, column: 1, ref token }) if token == "99999999999"
+    ));
+    // A sector of 3 fields, a wall of 5
+    assert!(matches!(
+        parse("1\n0 4 40\n"),
+        Err(MapError::FieldCount { line: 2, kind: MapLine::Sector, found: 3, expected: [4, 6, 7, 10] })
+    ));
+    assert!(matches!(
+        parse("0\n1\n0 0 32 0 255\n"),
+        Err(MapError::FieldCount { line: 3, kind: MapLine::Wall, found: 5, expected: [4, 7, 8] })
+    ));
+    // Cut after the sectors, and before the player
+    assert!(matches!(
+        parse("1\n0 4 40 0\n"),
+        Err(MapError::UnexpectedEof { line: 3, expected: MapLine::Count })
+    ));
+    assert!(matches!(
+        parse("0\n0\n\n"),
+        Err(MapError::UnexpectedEof { line: 4, expected: MapLine::Player })
+    ));
+    // The player right after the walls
+    assert!(matches!(
+        parse("0\n0\n70 -110 20 0 0\n"),
+        Err(MapError::MissingSeparator { line: 3 })
+    ));
+    assert!(parse("0\n0\n\n70 -110 20 0 0\n").is_ok());
+}
+
+#[test]
+fn ron_rejects_newer_version() {
+    let map = Map::from("assets/box.map").unwrap();
+    let text = map.to_ron().unwrap().replacen("version: 1", "version: 99", 1);
+    assert!(Map::from_ron(&text).is_err());
+}
+
+#[test]
+fn ron_keeps_player_start() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    // Angles written as integers, like the files of the first version
+    assert!(fs::read_to_string("assets/rooms.ron").unwrap().contains("angle: 90,"));
+    assert_eq!(map.player.borrow().angle, 90.0);
+    *map.player.borrow_mut() = Player::new_with_position_angles(Vec3::new(8, -4, 20), 37.5, -12.25);
+    let reloaded = Map::from_ron(&map.to_ron().unwrap()).unwrap();
+    let player = reloaded.player.borrow();
+    assert_eq!((player.position, player.angle, player.updown), (Vec3::new(8, -4, 20), 37.5, -12.25));
+}
+
+#[test]
+fn things_only_in_ron() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    assert!(!map.world.things.is_empty());
+    // Stored even though sprites have no rotation frames
+    assert_eq!(map.world.things[1].facing, 90.0);
+    let reloaded = Map::from_ron(&map.to_ron().unwrap()).unwrap();
+    assert_eq!(map.world.things, reloaded.world.things);
+    assert!(matches!(map.to_string(), Err(MapError::Things { count: 3 })));
+}
+
+#[test]
+fn blend_only_in_ron() {
+    let mut map = Map::from("assets/box.map").unwrap();
+    let mut world = (*map.world).clone();
+    world.walls[1].blend = Blend::Masked;
+    world.walls[1].middle = Some(world.walls[0].material);
+    map.world = Rc::new(world);
+    assert!(matches!(map.to_string(), Err(MapError::Unrepresentable { kind: MapLine::Wall, index: 1 })));
+    let reloaded = Map::from_ron(&map.to_ron().unwrap()).unwrap();
+    assert_eq!(reloaded.world.walls[1].blend, Blend::Masked);
+    assert_eq!(reloaded.world.walls[1].middle, Some(map.world.walls[0].material));
+    assert_eq!(map.world, reloaded.world);
+}
+
+#[test]
+fn lower_step_only_in_ron() {
+    let mut map = Map::from("assets/box.map").unwrap();
+    let mut world = (*map.world).clone();
+    world.walls[1].lower = Some(Material::Color([0, 0xff, 0, 0xff]));
+    map.world = Rc::new(world);
+    assert!(matches!(map.to_string(), Err(MapError::Unrepresentable { kind: MapLine::Wall, index: 1 })));
+    let reloaded = Map::from_ron(&map.to_ron().unwrap()).unwrap();
+    assert_eq!(reloaded.world.walls[1].lower, Some(Material::Color([0, 0xff, 0, 0xff])));
+}
+
+#[test]
+fn light_only_in_ron() {
+    let mut map = Map::from("assets/box.map").unwrap();
+    assert!(map.world.sectors.iter().all(|sector| sector.light == FULL_LIGHT));
+    let mut world = (*map.world).clone();
+    world.sectors[0].light = 96;
+    map.world = Rc::new(world);
+    assert!(matches!(map.to_string(), Err(MapError::Unrepresentable { kind: MapLine::Sector, index: 0 })));
+    let text = map.to_ron().unwrap();
+    assert_eq!(Map::from_ron(&text).unwrap().world.sectors[0].light, 96);
+    // Sectors written before lights are full bright
+    let reloaded = Map::from_ron(&text.replace("light: 96,", "")).unwrap();
+    assert_eq!(reloaded.world.sectors[0].light, FULL_LIGHT);
+}
@@ -0,0 +1,136 @@
+use rust_doom_demo::clock::Clock;
+use rust_doom_demo::consts;
+use rust_doom_demo::map::Map;
+use rust_doom_demo::math::Vec3;
+use rust_doom_demo::player::{MouseSettings, Player, PlayerInput};
+use rust_doom_demo::world::World;
+use std::time::Duration;
+
+
+// A second of walking at the given frame rate, frames are a bit longer for rounding
+fn walk(frames: u32) -> Player {
+    let world = World { walls: vec![], sectors: vec![], things: vec![] };
+    let mut clock = Clock::new(consts::TICK_RATE, Duration::from_secs_f32(consts::MAX_FRAME_TIME));
+    let mut player = Player::new_with_position(Vec3::new(0, 0, 0));
+    for _ in 0..frames {
+        for _ in 0..clock.advance(Duration::from_secs(1) / frames + Duration::from_micros(1)) {
+            player.tick(&world, &PlayerInput { forward: 1, turn: 1, ..Default::default() }, clock.delta());
+        }
+    }
+    player
+}
+
+#[test]
+fn frame_rate_independent() {
+    let slow = walk(20);
+    assert_eq!(slow, walk(35));
+    assert_eq!(slow, walk(144));
+    assert!((slow.angle - consts::ROTATION_VELOCITY).abs() < 1e-3);
+}
+
+#[test]
+fn long_frames_are_cut() {
+    let mut clock = Clock::new(consts::TICK_RATE, Duration::from_secs_f32(consts::MAX_FRAME_TIME));
+    assert_eq!(clock.advance(Duration::from_secs(10)), (consts::TICK_RATE as f32 * consts::MAX_FRAME_TIME) as u32);
+}
+
+#[test]
+fn interpolation() {
+    let start = Player::new_with_position_angles(Vec3::new(0, 0, 0), 359.0, 0.0);
+    let mut next = start;
+    next.translate(Vec3::new(10.0, 0.0, 0.0));
+    next.turn(2.0);
+    assert_eq!(start.interpolate(&next, 0.0), start);
+    let half = start.interpolate(&next, 0.5);
+    assert_eq!(half.position, Vec3::new(5, 0, 0));
+    // Across 0 degrees the short way
+    assert!(half.angle < 1e-3);
+}
+
+#[test]
+fn mouse_look() {
+    let world = World { walls: vec![], sectors: vec![], things: vec![] };
+    let mut player = Player::new();
+    let mouse = MouseSettings { sensitivity: 0.1, invert_y: false };
+    let input = PlayerInput { mouse: mouse.motion((-5.0, 50.0)), ..Default::default() };
+    player.tick(&world, &input, 1.0 / consts::TICK_RATE as f32);
+    // Half a degree to the left, below a whole degree
+    assert!((player.angle - 359.5).abs() < 1e-3);
+    assert!((player.updown - 5.0).abs() < 1e-3);
+    let inverted = MouseSettings { invert_y: true, ..mouse };
+    assert_eq!(inverted.motion((0.0, 50.0)).y, -5.0);
+    // Looking stops at the limit
+    player.look(1000.0);
+    assert_eq!(player.updown, consts::MAX_UPDOWN);
+}
+
+// Ticks of the input in box.map, a box from -16 to 16
+fn walk_in_box(mut player: Player, input: PlayerInput, ticks: u32) -> Player {
+    let map = Map::from("assets/box.map").unwrap();
+    for _ in 0..ticks {
+        player.tick(&map.world, &input, 1.0 / consts::TICK_RATE as f32);
+    }
+    player
+}
+
+#[test]
+fn walls_stop_the_player() {
+    let forward = PlayerInput { north: 1, ..Default::default() };
+    let mut start = Player::new_with_position(Vec3::new(0, -70, 40));
+    let player = walk_in_box(start, forward, 70);
+    assert_eq!(player.position.y, -16 - consts::PLAYER_RADIUS as i32);
+    // Noclip walks through
+    start.noclip = true;
+    assert!(walk_in_box(start, forward, 70).position.y > 16);
+}
+
+#[test]
+fn walls_slide_the_player() {
+    let start = Player::new_with_position(Vec3::new(-10, -30, 40));
+    // 2 units a tick, the wall is hit after 5 ticks
+    let player = walk_in_box(start, PlayerInput { north: 1, east: 1, ..Default::default() }, 10);
+    assert_eq!(player.position.y, -16 - consts::PLAYER_RADIUS as i32);
+    assert_eq!(player.position.x, 10);
+}
+
+// Seconds of the input from the start of the map, walking east
+fn walk_in_rooms(world: &World, input: PlayerInput, seconds: u32) -> Player {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let mut player = *map.player.borrow();
+    for _ in 0..consts::TICK_RATE * seconds {
+        player.tick(world, &input, 1.0 / consts::TICK_RATE as f32);
+    }
+    player
+}
+
+#[test]
+fn stands_on_the_floor() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    // Starts in the air of the hall
+    let player = walk_in_rooms(&map.world, PlayerInput::default(), 1);
+    assert_eq!(player.position.z, consts::EYE_HEIGHT as i32);
+    // Up the corridor, down the tall room to the pillar
+    let player = walk_in_rooms(&map.world, PlayerInput { forward: 1, ..Default::default() }, 3);
+    assert_eq!(player.position.x, 112 - consts::PLAYER_RADIUS as i32);
+    assert_eq!(player.feet(), -8.0);
+    // Flying keeps the height
+    let fly = PlayerInput { fly: 1, ..Default::default() };
+    let player = walk_in_rooms(&map.world, fly, 1);
+    assert!(player.flying);
+    assert!(player.position.z > 32);
+}
+
+#[test]
+fn ledges_and_ceilings_block() {
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let forward = PlayerInput { forward: 1, ..Default::default() };
+    let portal = 40 - consts::PLAYER_RADIUS as i32;
+    // A corridor floor too high to step on
+    let mut world = (*map.world).clone();
+    world.sectors[1].height.bottom = consts::STEP_HEIGHT as i32 + 1;
+    assert_eq!(walk_in_rooms(&world, forward, 2).position.x, portal);
+    // A corridor too low to walk in
+    let mut world = (*map.world).clone();
+    world.sectors[1].height.top = 8 + consts::PLAYER_HEIGHT as i32 - 1;
+    assert_eq!(walk_in_rooms(&world, forward, 2).position.x, portal);
+}
@@ -0,0 +1,397 @@
+use rust_doom_demo::bsp::Bsp;
+use rust_doom_demo::consts;
+use rust_doom_demo::framebuffer::{Canvas, ColumnWriter, FrameBuffer};
+use rust_doom_demo::math::{Vec2, Vec3};
+use rust_doom_demo::map::Map;
+use rust_doom_demo::player::Player;
+use rust_doom_demo::render::{Render, RenderConfig, RenderMode};
+use rust_doom_demo::texture::{Texture, TextureSet};
+use rust_doom_demo::world::{Blend, Material, Sector, SectorHeight, TextureMapping, Thing, Wall, World, FULL_LIGHT};
+use std::rc::Rc;
+
+fn render(render: &mut Render, map: &Map) -> Canvas {
+    let mut canvas = Canvas::new(render.config.width as usize, render.config.height as usize);
+    canvas.clear(consts::BACKGROUND_COLOR);
+    render.draw(&mut canvas, &map.player.borrow());
+    canvas
+}
+
+// Pixels of each column that are not background
+fn drawn_columns(canvas: &Canvas) -> Vec<usize> {
+    let mut columns = vec![0; canvas.dimensions.x];
+    for (index, pixel) in canvas.buffer().chunks_exact(4).enumerate() {
+        if pixel != consts::BACKGROUND_COLOR {
+            columns[index % canvas.dimensions.x] += 1;
+        }
+    }
+    columns
+}
+
+#[test]
+fn headless_frame() {
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    let map = Map::from("assets/box.map").unwrap();
+    let mut render = Render::new(Rc::clone(&map.world), textures, RenderConfig::default());
+    let canvas = self::render(&mut render, &map);
+    let background = canvas
+        .buffer()
+        .chunks_exact(4)
+        .filter(|pixel| *pixel == consts::BACKGROUND_COLOR)
+        .count();
+    assert!(background < (consts::WIDTH * consts::HEIGHT) as usize);
+}
+
+#[test]
+fn runtime_resolution() {
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let mut render = Render::new(Rc::clone(&map.world), textures, RenderConfig::new(320, 200, 2));
+    let canvas = self::render(&mut render, &map);
+    assert_eq!(canvas.dimensions.x, 320);
+    // Inside of a room every column is drawn
+    assert!(drawn_columns(&canvas).iter().all(|count| *count > 0));
+    render.resize(640, 480);
+    assert_eq!(render.config.scale, 2);
+    let canvas = self::render(&mut render, &map);
+    assert!(drawn_columns(&canvas).iter().all(|count| *count == 480));
+}
+
+#[test]
+fn field_of_view() {
+    let mut config = RenderConfig::default();
+    let focal = config.width_on_fov();
+    config.set_fov(config.fov + 20.0);
+    assert!(config.width_on_fov() < focal);
+    config.set_fov(1000.0);
+    assert_eq!(config.fov, consts::MAX_FOV);
+    // Aspect correction only changes frames wider than 4:3
+    let mut wide = RenderConfig::new(consts::WIDTH, consts::HEIGHT, 1);
+    wide.aspect_correct = true;
+    assert_eq!(wide.width_on_fov(), RenderConfig::default().width_on_fov());
+    wide.width *= 2;
+    assert!((wide.horizontal_fov() - wide.fov).abs() > 10.0);
+    assert!(wide.horizontal_fov() > wide.fov);
+}
+
+#[test]
+fn bands_match_one_thread() {
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    for path in ["assets/4tw.map", "assets/box.map", "assets/rooms.ron"] {
+        let mut map = Map::from(path).unwrap();
+        map.build_bsp();
+        for threads in [3, 7] {
+            let mut config = RenderConfig::new(317, 200, 1);
+            let mut single = Render::new(Rc::clone(&map.world), Rc::clone(&textures), RenderConfig { threads: 1, ..config });
+            config.threads = threads;
+            let mut bands = Render::new(Rc::clone(&map.world), Rc::clone(&textures), config);
+            assert_eq!(render(&mut single, &map), render(&mut bands, &map), "{} {} threads", path, threads);
+            let bsp = Rc::clone(map.bsp.as_ref().unwrap());
+            let mut single = single.with_bsp(Rc::clone(&bsp));
+            let mut bands = bands.with_bsp(bsp);
+            assert_eq!(render(&mut single, &map), render(&mut bands, &map), "{} {} threads, BSP", path, threads);
+        }
+    }
+}
+
+#[test]
+fn column_writer_matches_draw_pixel() {
+    let color = |x: usize, y: usize| [x as u8, y as u8, 7, 0xff];
+    let mut pixels = Canvas::new(13, 9);
+    let mut columns = pixels.clone();
+    let mut bands = pixels.clone();
+    for x in 0..13 {
+        // Rows past the top are left out
+        for y in x % 3..12 {
+            pixels.draw_pixel(&Vec2::new(x, y), &color(x, y));
+        }
+        ColumnWriter::new(&mut columns).column(x, x % 3..12, |y| color(x, y));
+    }
+    // Each band writes its columns, the ones of the other bands are left out
+    for mut writer in ColumnWriter::bands(&mut bands, &[0..4, 4..9, 9..13]) {
+        for x in 0..13 {
+            writer.column(x, x % 3..12, |y| color(x, y));
+        }
+    }
+    assert_eq!(pixels, columns);
+    assert_eq!(pixels, bands);
+    let mut frame = Canvas::new(13, 9);
+    for x in 4..9 {
+        ColumnWriter::new(&mut frame).column(x, x % 3..12, |y| color(x, y));
+    }
+    let mut band = Canvas::new(13, 9);
+    for mut writer in ColumnWriter::bands(&mut band, &[4..9]) {
+        for x in 0..13 {
+            writer.column(x, x % 3..12, |y| color(x, y));
+        }
+    }
+    assert_eq!(frame, band);
+}
+
+const RED: [u8; 4] = [0xff, 0, 0, 0xff];
+const GREEN: [u8; 4] = [0, 0xff, 0, 0xff];
+const BLUE: [u8; 4] = [0, 0, 0xff, 0xff];
+
+// Walls of a closed loop of points
+fn loop_walls(points: &[(i32, i32)]) -> Vec<Wall> {
+    (0..points.len())
+        .map(|i| {
+            let (p1, p2) = (points[i], points[(i + 1) % points.len()]);
+            Wall::new(&Vec2::new(p1.0, p1.1), &Vec2::new(p2.0, p2.1))
+        })
+        .collect()
+}
+
+// Square room with a pillar on the way north and a thing behind it, or beside it
+fn pillar_room(thing_x: i32, pillar: bool) -> World {
+    let mut walls = loop_walls(&[(-64, -64), (-64, 64), (64, 64), (64, -64)]);
+    if pillar {
+        walls.extend(loop_walls(&[(-8, 0), (8, 0), (8, 8), (-8, 8)]));
+    }
+    let sector = Sector::new_with_colors(
+        &Vec2::new(0, walls.len() as i32),
+        &SectorHeight::new(64, 0),
+        [BLUE, GREEN]
+    );
+    World {
+        walls,
+        sectors: vec![sector],
+        things: vec![Thing::new(&Vec3::new(thing_x, 40, 0), 16, 0)],
+    }
+}
+
+// Left half transparent, right half red
+fn half_red_textures() -> TextureSet {
+    let mut textures = TextureSet::new();
+    textures.set.push(Texture {
+        dimensions: Vec2::new(2, 4),
+        channels: 4,
+        data: [[0, 0, 0, 0], RED].repeat(4).concat(),
+    });
+    textures
+}
+
+// Frame of each render mode, the painter, portals and BSP
+fn mode_frames(world: &World, textures: &Rc<TextureSet>, player: &Player) -> Vec<Canvas> {
+    let bsp = Rc::new(Bsp::build(world));
+    [RenderMode::Sectors, RenderMode::Portals, RenderMode::Bsp]
+        .into_iter()
+        .map(|mode| {
+            let mut render = Render::new(Rc::new(world.clone()), Rc::clone(textures), RenderConfig::new(160, 120, 1))
+                .with_bsp(Rc::clone(&bsp));
+            render.mode = mode;
+            let mut canvas = Canvas::new(160, 120);
+            canvas.clear(consts::BACKGROUND_COLOR);
+            render.draw(&mut canvas, player);
+            canvas
+        })
+        .collect()
+}
+
+fn pixels(canvas: &Canvas) -> impl Iterator<Item = [u8; 4]> + '_ {
+    canvas.buffer().chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+}
+
+// Pixels changed by the thing, in each render mode
+fn sprite_pixels(world: World) -> Vec<Vec<[u8; 4]>> {
+    let textures = Rc::new(half_red_textures());
+    let player = Player::new_with_position_angles(Vec3::new(0, -40, 32), 0.0, 0.0);
+    let empty = World { things: vec![], ..world.clone() };
+    mode_frames(&world, &textures, &player)
+        .iter()
+        .zip(mode_frames(&empty, &textures, &player).iter())
+        .map(|(with, without)| {
+            pixels(with)
+                .zip(pixels(without))
+                .filter(|(a, b)| a != b)
+                .map(|(a, _)| a)
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn sprites_skip_transparent_texels() {
+    for (mode, pixels) in sprite_pixels(pillar_room(24, true)).iter().enumerate() {
+        assert!(!pixels.is_empty(), "mode {}", mode);
+        assert!(pixels.iter().all(|pixel| *pixel == RED), "mode {}", mode);
+    }
+}
+
+#[test]
+fn walls_hide_sprites() {
+    for (mode, pixels) in sprite_pixels(pillar_room(0, true)).iter().enumerate() {
+        assert!(pixels.is_empty(), "mode {}", mode);
+    }
+    for (mode, pixels) in sprite_pixels(pillar_room(0, false)).iter().enumerate() {
+        assert!(!pixels.is_empty(), "mode {}", mode);
+    }
+}
+
+// Room with a step of its own sector on the way north, above the eye, and a taller thing behind
+fn step_room(step: bool) -> World {
+    let mut walls = loop_walls(&[(-64, -64), (-64, 64), (64, 64), (64, -64)]);
+    let mut sectors = vec![Sector::new_with_colors(&Vec2::new(0, 4), &SectorHeight::new(96, 0), [BLUE, GREEN])];
+    if step {
+        walls.extend(loop_walls(&[(-8, 0), (8, 0), (8, 8), (-8, 8)]));
+        sectors.push(Sector::new_with_colors(&Vec2::new(4, 8), &SectorHeight::new(40, 0), [BLUE, BLUE]));
+    }
+    World {
+        walls,
+        sectors,
+        things: vec![Thing::new(&Vec3::new(0, 24, 0), 56, 0)],
+    }
+}
+
+// Red texels of the thing in each row of the painter's frame, from the top
+fn painted_sprite_rows(world: World) -> Vec<usize> {
+    let textures = Rc::new(half_red_textures());
+    let player = Player::new_with_position_angles(Vec3::new(0, -40, 32), 0.0, 0.0);
+    let frame = &mode_frames(&world, &textures, &player)[0];
+    frame
+        .buffer()
+        .chunks_exact(160 * 4)
+        .map(|row| row.chunks_exact(4).filter(|pixel| *pixel == RED).count())
+        .collect()
+}
+
+#[test]
+fn steps_hide_only_their_rows() {
+    // The painter shows the top of the thing over the step, hidden behind it
+    let over = painted_sprite_rows(step_room(true));
+    let whole = painted_sprite_rows(step_room(false));
+    assert!(over[0] > 0 && over[0] == whole[0]);
+    assert!(over[119] < whole[119]);
+}
+
+// Long red block in front of a small green one, seen from out of every sector
+fn blocks() -> World {
+    let mut walls = loop_walls(&[(-200, 40), (200, 40), (200, 48), (-200, 48)]);
+    walls.extend(loop_walls(&[(-8, 64), (8, 64), (8, 72), (-8, 72)]));
+    for (index, wall) in walls.iter_mut().enumerate() {
+        wall.material = Material::Color(if index < 4 { RED } else { GREEN });
+    }
+    World {
+        walls,
+        sectors: vec![
+            Sector::new_with_colors(&Vec2::new(0, 4), &SectorHeight::new(40, 0), [BLUE, BLUE]),
+            Sector::new_with_colors(&Vec2::new(4, 8), &SectorHeight::new(40, 0), [BLUE, BLUE]),
+        ],
+        things: vec![],
+    }
+}
+
+#[test]
+fn bsp_paints_out_of_sectors() {
+    let textures = Rc::new(TextureSet::new());
+    let player = Player::new_with_position_angles(Vec3::new(0, 0, 20), 0.0, 0.0);
+    let frames = mode_frames(&blocks(), &textures, &player);
+    let center = |canvas: &Canvas| pixels(canvas).nth(60 * 160 + 80).unwrap();
+    // The average distance of its walls puts the long block behind the small one
+    assert_eq!(center(&frames[0]), GREEN);
+    assert_eq!(center(&frames[2]), RED);
+}
+
+// Blue room looking north through a portal into a green one, the portal has the half red texture
+fn window_rooms(blend: Blend) -> World {
+    let mut walls = loop_walls(&[(-32, -32), (-32, 32), (32, 32), (32, -32)]);
+    walls.extend(loop_walls(&[(-32, 32), (-32, 96), (32, 96), (32, 32)]));
+    for wall in &mut walls[..4] {
+        wall.material = Material::Color(BLUE);
+    }
+    for wall in &mut walls[4..] {
+        wall.material = Material::Color(GREEN);
+    }
+    walls[1] = Wall {
+        blend,
+        ..Wall::new_portal(&walls[1].point1, &walls[1].point2, Material::Texture(TextureMapping {
+            texture: 0,
+            uv: Vec2::new(4, 1),
+            shade: 0
+        }), 1)
+    };
+    walls[7].portal = Some(0);
+    let height = SectorHeight::new(64, 0);
+    World {
+        walls,
+        sectors: vec![
+            Sector::new_with_colors(&Vec2::new(0, 4), &height, [BLUE, BLUE]),
+            Sector::new_with_colors(&Vec2::new(4, 8), &height, [GREEN, GREEN]),
+        ],
+        things: vec![],
+    }
+}
+
+#[test]
+fn masked_and_translucent_walls() {
+    let textures = Rc::new(half_red_textures());
+    let player = Player::new_with_position_angles(Vec3::new(0, -16, 32), 0.0, 0.0);
+    for (mode, frame) in mode_frames(&window_rooms(Blend::Masked), &textures, &player).iter().enumerate() {
+        // The room behind shows between the red texels, none of the transparent ones is drawn
+        assert!(pixels(frame).any(|pixel| pixel == RED), "mode {}", mode);
+        assert!(pixels(frame).any(|pixel| pixel == GREEN), "mode {}", mode);
+        assert!(pixels(frame).all(|pixel| pixel[..3] != [0, 0, 0]), "mode {}", mode);
+    }
+    // Mixed with the room behind, which the painter may draw last from inside of a room
+    for (mode, frame) in mode_frames(&window_rooms(Blend::Translucent(128)), &textures, &player).iter().enumerate().skip(1) {
+        assert!(pixels(frame).all(|pixel| pixel != RED), "mode {}", mode);
+        assert!(pixels(frame).any(|pixel| pixel == [128, 127, 0, 0xff]), "mode {}", mode);
+    }
+}
+
+// Through a portal to a room with a higher floor and a lower ceiling, each step keeps its material
+#[test]
+fn steps_keep_their_materials() {
+    let yellow = [0xff, 0xff, 0, 0xff];
+    let mut world = window_rooms(Blend::Opaque);
+    world.walls[1].material = Material::Color(RED);
+    world.walls[1].lower = Some(Material::Color(yellow));
+    world.sectors[1].height = SectorHeight::new(40, 24);
+    let player = Player::new_with_position_angles(Vec3::new(0, -16, 32), 0.0, 0.0);
+    for (mode, frame) in mode_frames(&world, &Rc::new(TextureSet::new()), &player).iter().enumerate().skip(1) {
+        // Middle column, from the top
+        let column: Vec<[u8; 4]> = pixels(frame).skip(80).step_by(160).collect();
+        let first = |color: [u8; 4]| column.iter().position(|pixel| *pixel == color);
+        assert!(matches!((first(RED), first(GREEN), first(yellow)), (Some(upper), Some(opening), Some(lower)) if upper < opening && opening < lower), "mode {}", mode);
+    }
+}
+
+#[test]
+fn blended_column() {
+    let mut frame = Canvas::new(1, 4);
+    frame.clear(BLUE);
+    ColumnWriter::new(&mut frame).blended_column(0, 0..4, |y| [0xff, 0, 0, [0, 0x40, 0x80, 0xff][y]]);
+    let column: Vec<[u8; 4]> = pixels(&frame).collect();
+    // Rows are stored top to bottom
+    assert_eq!(column, vec![RED, [128, 0, 127, 0xff], [64, 0, 191, 0xff], BLUE]);
+}
+
+// White corridor going north, with a thing far down of it
+fn corridor(light: u8) -> World {
+    let walls = loop_walls(&[(-32, -32), (-32, 1024), (32, 1024), (32, -32)]);
+    let sector = Sector::new(&Vec2::new(0, walls.len() as i32), &SectorHeight::new(64, 0));
+    World {
+        walls,
+        sectors: vec![Sector { light, ..sector }],
+        things: vec![Thing::new(&Vec3::new(0, 512, 0), 64, 0)],
+    }
+}
+
+#[test]
+fn light_diminishes_with_distance() {
+    let textures = Rc::new(half_red_textures());
+    let player = Player::new_with_position_angles(Vec3::new(0, -16, 32), 0.0, 0.0);
+    // Full light, as bright near and far, the painter leaves a few background gaps
+    for (mode, frame) in mode_frames(&corridor(FULL_LIGHT), &textures, &player).iter().enumerate() {
+        let mut lit = pixels(frame).filter(|pixel| *pixel != consts::BACKGROUND_COLOR);
+        assert!(lit.all(|pixel| pixel == [0xff; 4] || pixel == RED), "mode {}", mode);
+    }
+    for (mode, frame) in mode_frames(&corridor(128), &textures, &player).iter().enumerate() {
+        // The wall beside the player is lighter than the wall far down, both gray
+        let (near, far) = (frame.pixel(0, 60), frame.pixel(70, 60));
+        assert!(far[0] < near[0] && near[0] < 0xff, "mode {}", mode);
+        assert!(near[0] == near[1] && near[1] == near[2], "mode {}", mode);
+        // The thing is darkened like the walls around it
+        assert!(pixels(frame).all(|pixel| pixel != RED), "mode {}", mode);
+        assert!(pixels(frame).any(|pixel| pixel[0] > 0 && pixel[1..3] == [0, 0]), "mode {}", mode);
+    }
+}
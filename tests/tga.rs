@@ -0,0 +1,35 @@
+use rust_doom_demo::tga::{decode_tga, encode_tga};
+
+// Decoded images are stored bottom row first
+fn flip_rows(image: &[u8], width: usize, channels: usize) -> Vec<u8> {
+    image.chunks_exact(width * channels).rev().flatten().copied().collect()
+}
+
+fn round_trip(channels: u8, rle: bool) {
+    let (width, height) = (7usize, 5usize);
+    // Runs and noise, to exercise both RLE packet types
+    let image: Vec<u8> = (0..width * height * channels as usize)
+        .map(|i| if i < 40 { 0x20 } else { (i * 37 % 251) as u8 })
+        .collect();
+    let mut out_tga = Vec::new();
+    assert!(encode_tga(&mut out_tga, &image, width, height, channels, rle));
+    let (mut data, mut decoded_width, mut decoded_height, mut format, mut colors) = (vec![], 0, 0, 0, 0);
+    assert!(decode_tga(&mut data, &mut decoded_width, &mut decoded_height, &mut format, &mut colors, &out_tga));
+    assert_eq!((decoded_width, decoded_height, colors), (width, height, channels));
+    assert_eq!(data, flip_rows(&image, width, channels as usize));
+}
+
+#[test]
+fn encode_decode() {
+    round_trip(3, false);
+    round_trip(3, true);
+    round_trip(4, false);
+    round_trip(4, true);
+}
+
+#[test]
+fn encode_rejects_bad_input() {
+    let mut out_tga = Vec::new();
+    assert!(!encode_tga(&mut out_tga, &[0; 12], 2, 2, 4, false));
+    assert!(!encode_tga(&mut out_tga, &[0; 4], 2, 2, 1, false));
+}
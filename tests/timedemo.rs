@@ -0,0 +1,36 @@
+use rust_doom_demo::map::Map;
+use rust_doom_demo::render::{Render, RenderConfig};
+use rust_doom_demo::texture::TextureSet;
+use rust_doom_demo::timedemo::{self, FrameStats};
+use std::rc::Rc;
+use std::time::Duration;
+
+#[test]
+fn frame_stats() {
+    let times: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+    let stats = FrameStats::from_times(&times).unwrap();
+    assert_eq!(stats.frames, 100);
+    assert_eq!(stats.min, Duration::from_millis(1));
+    assert_eq!(stats.max, Duration::from_millis(100));
+    assert_eq!(stats.p50, Duration::from_millis(50));
+    assert_eq!(stats.p95, Duration::from_millis(95));
+    assert_eq!(stats.p99, Duration::from_millis(99));
+    assert_eq!(stats.average, Duration::from_micros(50500));
+    assert!((stats.fps() - 100.0 / 5.05).abs() < 1e-3);
+    assert!(FrameStats::from_times(&[]).is_none());
+}
+
+#[test]
+fn orbit_of_the_start() {
+    let textures = Rc::new(TextureSet::from("assets/textures").unwrap());
+    let map = Map::from("assets/rooms.ron").unwrap();
+    let start = *map.player.borrow();
+    let path = timedemo::orbit(&start, 12);
+    assert_eq!(path.len(), 12);
+    for (index, player) in path.iter().enumerate() {
+        assert!((player.position.xy().as_vec::<f32>().distance(&start.position.xy().as_vec::<f32>()) - 8.0).abs() <= 1.5);
+        assert!(((player.angle - start.angle).rem_euclid(360.0) - index as f32 * 30.0).abs() < 1e-3);
+    }
+    let mut render = Render::new(Rc::clone(&map.world), textures, RenderConfig::default());
+    assert_eq!(timedemo::run(&mut render, &path).unwrap().frames, 12);
+}
@@ -0,0 +1,259 @@
+use rust_doom_demo::consts;
+use rust_doom_demo::map::Map;
+use rust_doom_demo::math::{Vec2, Vec3};
+use rust_doom_demo::player::PlayerInput;
+use rust_doom_demo::texture::TextureSet;
+use rust_doom_demo::wad::{Wad, WadError};
+use rust_doom_demo::world::{Blend, Material};
+
+fn name(name: &str) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    bytes[..name.len()].copy_from_slice(name.as_bytes());
+    bytes
+}
+
+fn build_wad(lumps: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut directory = Vec::new();
+    data.extend_from_slice(b"PWAD");
+    data.extend_from_slice(&(lumps.len() as i32).to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    for (lump_name, lump) in lumps {
+        directory.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        directory.extend_from_slice(&(lump.len() as i32).to_le_bytes());
+        directory.extend_from_slice(&name(lump_name));
+        data.extend_from_slice(lump);
+    }
+    let offset = data.len() as i32;
+    data[8..12].copy_from_slice(&offset.to_le_bytes());
+    data.extend_from_slice(&directory);
+    data
+}
+
+fn shorts(values: &[i16]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn sidedef((upper, lower): (&str, &str), middle: &str, sector: i16) -> Vec<u8> {
+    let mut record = shorts(&[0, 0]);
+    record.extend_from_slice(&name(upper));
+    record.extend_from_slice(&name(lower));
+    record.extend_from_slice(&name(middle));
+    record.extend(shorts(&[sector]));
+    record
+}
+
+fn sector(floor: i16, ceiling: i16, light: i16) -> Vec<u8> {
+    let mut record = shorts(&[floor, ceiling]);
+    record.extend_from_slice(&name("FLOOR"));
+    record.extend_from_slice(&name("CEIL"));
+    record.extend(shorts(&[light, 0, 0]));
+    record
+}
+
+fn palette(index: u8) -> [u8; 3] {
+    [index, index.wrapping_mul(2), 255 - index]
+}
+
+// A 2x3 picture, the second column has a single texel in the middle
+fn picture() -> Vec<u8> {
+    let mut lump = shorts(&[2, 3, 0, 0]);
+    lump.extend_from_slice(&16i32.to_le_bytes());
+    lump.extend_from_slice(&24i32.to_le_bytes());
+    lump.extend_from_slice(&[0, 3, 0, 1, 2, 3, 0, 0xff]);
+    lump.extend_from_slice(&[1, 1, 0, 4, 0, 0xff]);
+    lump
+}
+
+fn texture_lumps() -> Vec<(&'static str, Vec<u8>)> {
+    let playpal: Vec<u8> = (0..=255u8).flat_map(palette).collect();
+    let mut pnames = 1i32.to_le_bytes().to_vec();
+    pnames.extend_from_slice(&name("WALL00_1"));
+    // STARTAN3, 4x3 with the picture on the two columns on the right
+    let mut texture1 = 1i32.to_le_bytes().to_vec();
+    texture1.extend_from_slice(&8i32.to_le_bytes());
+    texture1.extend_from_slice(&name("STARTAN3"));
+    texture1.extend_from_slice(&0i32.to_le_bytes());
+    texture1.extend(shorts(&[4, 3]));
+    texture1.extend_from_slice(&0i32.to_le_bytes());
+    texture1.extend(shorts(&[1, 2, 0, 0, 1, 0]));
+    vec![
+        ("PLAYPAL", playpal),
+        ("PNAMES", pnames),
+        ("TEXTURE1", texture1),
+        ("F_START", vec![]),
+        ("FLOOR", vec![7; 64 * 64]),
+        ("F_END", vec![]),
+        ("P_START", vec![]),
+        ("WALL00_1", picture()),
+        ("P_END", vec![]),
+        ("S_START", vec![]),
+        ("BAR1A0", picture()),
+        ("S_END", vec![]),
+    ]
+}
+
+fn two_rooms() -> Vec<u8> {
+    two_rooms_with_steps("-", "-")
+}
+
+// Two rooms side by side, joined by a two-sided linedef with a middle texture
+// and the upper and lower textures given on the side of the first room
+fn two_rooms_with_steps(upper: &str, lower: &str) -> Vec<u8> {
+    let vertexes = shorts(&[0, 0, 0, 128, 128, 128, 128, 0, 256, 128, 256, 0]);
+    let linedefs: Vec<u8> = [
+        [0, 1, 0, 0, 0, 0, -1],
+        [1, 2, 0, 0, 0, 1, -1],
+        [2, 3, 4, 0, 0, 2, 3],
+        [3, 0, 0, 0, 0, 4, -1],
+        [2, 4, 0, 0, 0, 5, -1],
+        [4, 5, 0, 0, 0, 6, -1],
+        [5, 3, 0, 0, 0, 7, -1],
+    ].iter().flat_map(|linedef| shorts(linedef)).collect();
+    let sidedefs: Vec<u8> = [0, 0, 0, 1, 0, 1, 1, 1]
+        .iter()
+        .enumerate()
+        .flat_map(|(index, sector)| sidedef(if index == 2 { (upper, lower) } else { ("-", "-") }, "STARTAN3", *sector))
+        .collect();
+    let sectors: Vec<u8> = [sector(0, 128, 160), sector(16, 100, 200)].concat();
+    let things = shorts(&[64, 64, 90, 1, 7, 192, 64, 0, 3004, 7, 192, 32, 180, 2035, 7]);
+    let mut lumps = vec![
+        ("MAP01", vec![]),
+        ("THINGS", things),
+        ("LINEDEFS", linedefs),
+        ("SIDEDEFS", sidedefs),
+        ("VERTEXES", vertexes),
+        ("SECTORS", sectors),
+    ];
+    lumps.extend(texture_lumps());
+    build_wad(&lumps)
+}
+
+#[test]
+fn import_level() {
+    let wad = Wad::from_bytes(two_rooms()).unwrap();
+    assert_eq!(wad.levels(), vec![String::from("MAP01")]);
+    let map = Map::from_wad(&wad, Some("MAP01"), None).unwrap();
+    let world = &map.world;
+    // Walls regrouped per sector, the shared linedef is in both
+    assert_eq!(world.sectors.len(), 2);
+    assert_eq!(world.sectors[0].wall, Vec2::new(0, 4));
+    assert_eq!(world.sectors[1].wall, Vec2::new(4, 8));
+    assert_eq!(world.walls[2].point1, Vec2::new(128, 128));
+    assert_eq!(world.walls[4].point1, Vec2::new(128, 0));
+    assert_eq!(world.walls[4].point2, Vec2::new(128, 128));
+    // Heights from SECTORS
+    assert_eq!(world.sectors[1].height.top, 100);
+    assert_eq!(world.sectors[1].height.bottom, 16);
+    // Light from SECTORS, the renderer darkens the sectors
+    assert_eq!((world.sectors[0].light, world.sectors[1].light), (160, 200));
+    assert!(world.validate(&TextureSet::new()).is_empty());
+    assert_eq!(world.sector_at(&Vec2::new(192.0, 64.0)), Some(1));
+    // Player start, facing north
+    let player = map.player.borrow();
+    assert_eq!(player.position, Vec3::new(64, 64, consts::EYE_HEIGHT as i32));
+    assert_eq!(player.angle, 0.0);
+    // Already standing, a second of ticks does not drop the player
+    let mut standing = *player;
+    for _ in 0..consts::TICK_RATE {
+        standing.tick(world, &PlayerInput::default(), 1.0 / consts::TICK_RATE as f32);
+    }
+    assert_eq!(standing.position, player.position);
+}
+
+#[test]
+fn missing_level() {
+    let wad = Wad::from_bytes(two_rooms()).unwrap();
+    assert!(Map::from_wad(&wad, Some("E1M1"), None).is_err());
+    assert!(Wad::from_bytes(b"JUNK".to_vec()).is_err());
+}
+
+#[test]
+fn import_textures() {
+    let wad = Wad::from_bytes(two_rooms()).unwrap();
+    let textures = TextureSet::from_wad(&wad).unwrap();
+    // Flat, raw 64x64 indices
+    let floor = &textures.set[textures.index("FLOOR").unwrap()];
+    assert_eq!(floor.dimensions, Vec2::new(64, 64));
+    assert_eq!(floor.pixel(10, 10), &[7, 14, 248, 255]);
+    // Patch, stored bottom row first with transparent texels
+    let patch = &textures.set[textures.index("wall00_1").unwrap()];
+    assert_eq!(patch.dimensions, Vec2::new(2, 3));
+    assert_eq!(patch.pixel(0, 2), &[1, 2, 254, 255]);
+    assert_eq!(patch.pixel(0, 0), &[3, 6, 252, 255]);
+    assert_eq!(patch.pixel(1, 1), &[4, 8, 251, 255]);
+    assert_eq!(patch.pixel(1, 0)[3], 0);
+    // Composite texture
+    let texture = textures.index("STARTAN3").unwrap();
+    let composite = &textures.set[texture];
+    assert_eq!(composite.dimensions, Vec2::new(4, 3));
+    assert_eq!(composite.pixel(0, 2)[3], 0);
+    assert_eq!(composite.pixel(2, 2), &[1, 2, 254, 255]);
+    assert_eq!(composite.pixel(3, 1), &[4, 8, 251, 255]);
+    // Level referencing the textures by name
+    let map = Map::from_wad(&wad, None, Some(&textures)).unwrap();
+    match map.world.walls[0].material {
+        Material::Texture(mapping) => assert_eq!((mapping.texture, mapping.shade), (texture, 0)),
+        _ => panic!("wall is not textured"),
+    }
+    // Middle texture of the two-sided linedef, drawn in the opening too
+    assert_eq!(map.world.walls[0].blend, Blend::Opaque);
+    assert_eq!(map.world.walls[2].blend, Blend::Masked);
+    match map.world.sectors[0].material[1] {
+        Material::Texture(mapping) => assert_eq!(mapping.texture, textures.index("FLOOR").unwrap()),
+        _ => panic!("floor is not textured"),
+    }
+    // Barrel on the floor of the second room, the monster has no sprite
+    assert_eq!(map.world.things.len(), 1);
+    let barrel = &map.world.things[0];
+    assert_eq!(barrel.texture, textures.index("BAR1A0").unwrap());
+    assert_eq!(barrel.position, Vec3::new(192, 32, 16));
+    assert_eq!((barrel.height, barrel.facing), (3, 270.0));
+    assert!(map.world.validate(&textures).is_empty());
+}
+
+#[test]
+fn malformed_texture_lumps() {
+    // Negative offset of the texture, huge offset, negative count of patches
+    let mut negative_count = texture_lumps()[2].1.clone();
+    negative_count[28..30].copy_from_slice(&(-1i16).to_le_bytes());
+    for texture1 in [
+        [1i32.to_le_bytes(), (-8i32).to_le_bytes()].concat(),
+        [1i32.to_le_bytes(), i32::MAX.to_le_bytes()].concat(),
+        (-1i32).to_le_bytes().to_vec(),
+        negative_count,
+    ] {
+        let mut lumps = texture_lumps();
+        lumps[2].1 = texture1;
+        let wad = Wad::from_bytes(build_wad(&lumps)).unwrap();
+        assert!(matches!(TextureSet::from_wad(&wad), Err(WadError::BadLump { lump: "TEXTURE1", .. })));
+    }
+}
+
+#[test]
+fn masked_middle_beside_steps() {
+    let wad = Wad::from_bytes(two_rooms_with_steps("FLOOR", "STARTAN3")).unwrap();
+    let textures = TextureSet::from_wad(&wad).unwrap();
+    let map = Map::from_wad(&wad, None, Some(&textures)).unwrap();
+    // Each step takes its texture, the opening keeps the middle one
+    let portal = &map.world.walls[2];
+    assert_eq!(portal.blend, Blend::Masked);
+    match (portal.material, portal.lower, portal.middle) {
+        (Material::Texture(upper), Some(Material::Texture(lower)), Some(Material::Texture(middle))) => {
+            assert_eq!(upper.texture, textures.index("FLOOR").unwrap());
+            assert_eq!(lower.texture, textures.index("STARTAN3").unwrap());
+            assert_eq!(middle.texture, textures.index("STARTAN3").unwrap());
+        },
+        _ => panic!("portal is not textured"),
+    }
+    // A lower texture alone is the material of both steps
+    let wad = Wad::from_bytes(two_rooms_with_steps("-", "FLOOR")).unwrap();
+    let map = Map::from_wad(&wad, None, Some(&textures)).unwrap();
+    let portal = &map.world.walls[2];
+    assert!(matches!(portal.material, Material::Texture(lower) if lower.texture == textures.index("FLOOR").unwrap()));
+    assert_eq!(portal.lower, None);
+    // Without textures there is nothing to mask
+    let map = Map::from_wad(&wad, None, None).unwrap();
+    assert_eq!((map.world.walls[2].blend, map.world.walls[2].middle), (Blend::Opaque, None));
+    assert!(map.world.validate(&TextureSet::new()).is_empty());
+}
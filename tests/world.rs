@@ -0,0 +1,117 @@
+use rust_doom_demo::math::{Vec2, Vec3};
+use rust_doom_demo::texture::{Texture, TextureSet};
+use rust_doom_demo::world::{Material, Sector, SectorHeight, TextureMapping, Thing, ValidationError, Wall, World};
+use std::fs;
+use std::process::Command;
+
+// Square room with a thing, valid with a single texture
+fn room() -> World {
+    let points = [(0, 0), (0, 64), (64, 64), (64, 0)];
+    let walls = (0..points.len())
+        .map(|i| {
+            let (p1, p2) = (points[i], points[(i + 1) % points.len()]);
+            Wall::new(&Vec2::new(p1.0, p1.1), &Vec2::new(p2.0, p2.1))
+        })
+        .collect();
+    World {
+        walls,
+        sectors: vec![Sector::new(&Vec2::new(0, 4), &SectorHeight::new(64, 0))],
+        things: vec![Thing::new(&Vec3::new(32, 32, 0), 16, 0)],
+    }
+}
+
+fn texture(texture: usize) -> Material {
+    Material::Texture(TextureMapping { texture, uv: Vec2::new(1, 1), shade: 0 })
+}
+
+fn errors(world: &World) -> Vec<ValidationError> {
+    // A single texture, for the thing
+    let mut textures = TextureSet::new();
+    textures.set.push(Texture { dimensions: Vec2::new(1, 1), channels: 4, data: vec![0xff; 4] });
+    world.validate(&textures)
+}
+
+#[test]
+fn valid_room() {
+    assert_eq!(errors(&room()), vec![]);
+}
+
+#[test]
+fn wall_range() {
+    let mut world = room();
+    world.sectors[0].wall = Vec2::new(2, 9);
+    assert_eq!(errors(&world), vec![ValidationError::WallRange { sector: 0, start: 2, end: 9, walls: 4 }]);
+}
+
+#[test]
+fn sector_height() {
+    let mut world = room();
+    world.sectors[0].height = SectorHeight::new(0, 64);
+    assert_eq!(errors(&world), vec![ValidationError::SectorHeight { sector: 0, top: 0, bottom: 64 }]);
+}
+
+#[test]
+fn open_loop() {
+    let mut world = room();
+    world.walls.pop();
+    world.sectors[0].wall = Vec2::new(0, 3);
+    assert_eq!(errors(&world), vec![
+        ValidationError::OpenLoop { sector: 0, point: Vec2::new(0, 0) },
+        ValidationError::OpenLoop { sector: 0, point: Vec2::new(64, 0) },
+    ]);
+}
+
+#[test]
+fn missing_textures() {
+    let mut world = room();
+    world.sectors[0].material[1] = texture(5);
+    world.walls[2].material = texture(3);
+    world.walls[3].middle = Some(texture(4));
+    assert_eq!(errors(&world), vec![
+        ValidationError::SectorTexture { sector: 0, material: 1, texture: 5 },
+        ValidationError::WallTexture { sector: Some(0), wall: 2, texture: 3 },
+        ValidationError::WallTexture { sector: Some(0), wall: 3, texture: 4 },
+    ]);
+}
+
+#[test]
+fn portals() {
+    // Out of the sectors, then back to its own sector
+    let mut world = room();
+    world.walls[1].portal = Some(7);
+    world.walls[2].portal = Some(0);
+    assert_eq!(errors(&world), vec![
+        ValidationError::Portal { sector: Some(0), wall: 1, neighbor: 7 },
+        ValidationError::Portal { sector: Some(0), wall: 2, neighbor: 0 },
+    ]);
+}
+
+#[test]
+fn things() {
+    let mut world = room();
+    world.things.push(Thing::new(&Vec3::new(16, 16, 0), 0, 2));
+    assert_eq!(errors(&world), vec![
+        ValidationError::ThingTexture { thing: 1, texture: 2 },
+        ValidationError::ThingHeight { thing: 1, height: 0 },
+    ]);
+}
+
+// --check prints the problems and exits with 1, or 0 for a valid map
+#[test]
+fn check_exit_code() {
+    let path = std::env::temp_dir().join("rust-doom-demo-check.map");
+    fs::write(&path, "1\n0 4 0 40\n4\n0 0 0 64\n0 64 64 64\n64 64 64 0\n64 0 0 0\n\n32 32 20 0 0\n").unwrap();
+    let check = |map: &str| {
+        Command::new(env!("CARGO_BIN_EXE_rust-doom-demo"))
+            .args(["--map", map, "--textures", "assets/textures", "--check"])
+            .output()
+            .unwrap()
+    };
+    let output = check(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("sector 0: top 0 is not above bottom 40"), "{}", stderr);
+    assert!(stderr.contains("1 problem(s) found"), "{}", stderr);
+    assert_eq!(check("assets/box.map").status.code(), Some(0));
+}